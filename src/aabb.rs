@@ -0,0 +1,98 @@
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::vec::Vec3;
+
+/// An axis-aligned bounding box, stored as its two extreme corners. These are
+/// the building blocks of the bounding volume hierarchy: testing a ray against a
+/// box is far cheaper than testing it against whatever is inside the box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn new(min: Point, max: Point) -> Self {
+        Aabb { min, max }
+    }
+
+    /// A box that contains nothing. Growing it by any point or box yields
+    /// exactly that point or box.
+    pub const EMPTY: Self = Self {
+        min: Point { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY },
+        max: Point { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY },
+    };
+
+    #[inline]
+    pub fn surrounding(&self, other: &Self) -> Self {
+        Aabb::new(self.min.min(&other.min), self.max.max(&other.max))
+    }
+
+    #[inline]
+    pub fn grow(&self, p: &Point) -> Self {
+        Aabb::new(self.min.min(p), self.max.max(p))
+    }
+
+    #[inline]
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) * 0.5
+    }
+
+    #[inline]
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.extent();
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 { return 0.0 }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Slab test against a ray. Takes the reciprocal of the ray direction so that
+    /// callers testing many boxes against the same ray only divide once.
+    #[inline]
+    pub fn hit_inv(&self, origin: &Point, inv_dir: &Vec3, t_min: f64, t_max: f64) -> bool {
+        self.clip_inv(origin, inv_dir, t_min, t_max).is_some()
+    }
+
+    /// The part of `[t_min, t_max]` for which the ray is inside the box, if any.
+    #[inline]
+    pub fn clip_inv(&self, origin: &Point, inv_dir: &Vec3, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (t0, t1) = slab(self.min.x, self.max.x, origin.x, inv_dir.x);
+        let t_min = t_min.max(t0);
+        let t_max = t_max.min(t1);
+        if t_max < t_min { return None }
+
+        let (t0, t1) = slab(self.min.y, self.max.y, origin.y, inv_dir.y);
+        let t_min = t_min.max(t0);
+        let t_max = t_max.min(t1);
+        if t_max < t_min { return None }
+
+        let (t0, t1) = slab(self.min.z, self.max.z, origin.z, inv_dir.z);
+        let t_min = t_min.max(t0);
+        let t_max = t_max.min(t1);
+        if t_max < t_min { return None }
+        Some((t_min, t_max))
+    }
+
+    #[allow(unused)]
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+
+    pub fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let inv_dir = Vec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+        self.clip_inv(&ray.origin, &inv_dir, t_min, t_max)
+    }
+}
+
+/// Entry and exit parameters of a ray through the slab `[min, max]` along one axis.
+/// A `NaN` from `0 * inf` (ray origin exactly on a slab plane and parallel to it)
+/// is dropped by the `min`/`max` in the caller, which treats the slab as passed.
+#[inline]
+fn slab(min: f64, max: f64, origin: f64, inv_dir: f64) -> (f64, f64) {
+    let t0 = (min - origin) * inv_dir;
+    let t1 = (max - origin) * inv_dir;
+    if inv_dir < 0.0 { (t1, t0) } else { (t0, t1) }
+}
@@ -0,0 +1,153 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+
+use crate::render::Color;
+use crate::renderer::Image;
+
+const MAGIC: &[u8; 8] = b"RTACCUM1";
+/// The magic number followed by width, height, seed and samples.
+const HEADER_BYTES: u64 = 8 + 4 * 8;
+/// Three channels of `f64` per pixel.
+const PIXEL_BYTES: u64 = 3 * 8;
+
+/// Running per-pixel sums of radiance for a progressive render, along with how
+/// many samples went into every pixel so far. Saving one of these to disk lets
+/// an interrupted render pick up where it left off.
+///
+/// The seed is kept too: sample `n` of a pixel always comes from the same
+/// generator (see `sample_rng`), so a render resumed from a checkpoint is
+/// identical to one that was never interrupted.
+#[derive(Clone, Debug)]
+pub struct Accumulation {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    pub samples: usize,
+    pub sums: Vec<Color>,
+}
+
+impl Accumulation {
+    pub fn new(width: usize, height: usize, seed: u64) -> Self {
+        Accumulation { width, height, seed, samples: 0, sums: vec![Color::default(); width * height] }
+    }
+
+    /// The image so far, as mean linear radiance per pixel.
+    pub fn image(&self) -> Image {
+        let scale = 1.0 / self.samples.max(1) as f64;
+        let pixels = self.sums.iter().map(|&sum| sum * scale).collect();
+        Image { width: self.width, height: self.height, pixels }
+    }
+
+    /// Writes the buffer next to `path` first and then moves it into place, so a
+    /// render killed mid-save still leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            out.write_all(MAGIC)?;
+            for value in [self.width as u64, self.height as u64, self.seed, self.samples as u64] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            for sum in self.sums.iter() {
+                for channel in [sum.x, sum.y, sum.z] {
+                    out.write_all(&channel.to_le_bytes())?;
+                }
+            }
+            out.flush()?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+        let file = File::open(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let file_len = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic).map_err(|_| invalid("not an accumulation file"))?;
+        if &magic != MAGIC { return Err(invalid("not an accumulation file")) }
+
+        let mut read_u64 = || -> Result<u64> {
+            let mut bytes = [0u8; 8];
+            input.read_exact(&mut bytes).map_err(|_| invalid("truncated accumulation file"))?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let width = read_u64()?;
+        let height = read_u64()?;
+        let seed = read_u64()?;
+        let samples = read_u64()?;
+
+        // Check the header against the size of the file before trusting it with
+        // an allocation.
+        let expected = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(PIXEL_BYTES))
+            .and_then(|bytes| bytes.checked_add(HEADER_BYTES));
+        if expected != Some(file_len) {
+            return Err(invalid(&format!("{} bytes is the wrong size for a {}x{} accumulation file", file_len, width, height)))
+        }
+        let too_large = |_| invalid("accumulation file is too large for this machine");
+        let (width, height) = (usize::try_from(width).map_err(too_large)?, usize::try_from(height).map_err(too_large)?);
+        let samples = usize::try_from(samples).map_err(too_large)?;
+
+        let mut sums = Vec::with_capacity(width * height);
+        for _ in 0..width * height {
+            let x = f64::from_bits(read_u64()?);
+            let y = f64::from_bits(read_u64()?);
+            let z = f64::from_bits(read_u64()?);
+            sums.push(Color::new(x, y, z));
+        }
+
+        Ok(Accumulation { width, height, seed, samples, sums })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ray-trace-{}-{}.accum", name, std::process::id()))
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = temp_path("round-trip");
+        let mut accum = Accumulation::new(3, 2, 42);
+        accum.samples = 5;
+        accum.sums[4] = Color::new(1.5, -0.0, f64::MAX);
+        accum.save(&path).unwrap();
+
+        let loaded = Accumulation::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.width, loaded.height, loaded.seed, loaded.samples), (3, 2, 42, 5));
+        assert_eq!(loaded.sums, accum.sums);
+    }
+
+    #[test]
+    fn header_must_match_the_file_size() {
+        let path = temp_path("bad-header");
+        Accumulation::new(3, 2, 42).save(&path).unwrap();
+        let good = fs::read(&path).unwrap();
+
+        let with_size = |width: u64, height: u64| {
+            let mut bytes = good.clone();
+            bytes[8..16].copy_from_slice(&width.to_le_bytes());
+            bytes[16..24].copy_from_slice(&height.to_le_bytes());
+            fs::write(&path, bytes).unwrap();
+            Accumulation::load(&path).unwrap_err().to_string()
+        };
+        // Too big to allocate, overflowing, and merely wrong.
+        assert!(with_size(1 << 31, 1 << 31).contains("wrong size"));
+        assert!(with_size(u64::MAX, 3).contains("wrong size"));
+        assert!(with_size(3, 3).contains("wrong size for a 3x3"));
+
+        fs::write(&path, &good[..good.len() - 1]).unwrap();
+        assert!(Accumulation::load(&path).unwrap_err().to_string().contains("wrong size"));
+        fs::write(&path, &good[..20]).unwrap();
+        assert!(Accumulation::load(&path).unwrap_err().to_string().contains("truncated"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use std::io::Result;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::hdr::{load_hdr, HdrImage};
+use crate::ray::Ray;
+use crate::render::{clamp, Color, PI};
+use crate::vec::Vec3;
+
+/// What a ray sees when it leaves the scene without hitting anything.
+#[derive(Clone, Debug)]
+pub enum Background {
+    Solid(Color),
+    /// Blends from `bottom` (looking along `-up`) to `top` (looking along `up`).
+    Gradient { bottom: Color, top: Color, up: Vec3 },
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Background {
+    /// The white to light blue sky from the book.
+    pub fn sky() -> Self {
+        Background::Gradient {
+            bottom: Color::new(1.0, 1.0, 1.0),
+            top: Color::new(0.5, 0.7, 1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Self::Solid(color) => *color,
+            Self::Gradient { bottom, top, up } => {
+                let t = (ray.dir.unit_vector().dot(&up.unit_vector()) + 1.0) * 0.5;
+                *bottom * (1.0 - t) + *top * t
+            }
+            Self::Environment(map) => map.lookup(&ray.dir),
+        }
+    }
+
+    /// Parses the `--background` argument: `sky`, `black`, `white`, an `r,g,b`
+    /// triple, a `gradient:r,g,b:r,g,b` from bottom to top color with an
+    /// optional `:x,y,z` up axis (+y by default), or the path to an
+    /// equirectangular `.hdr` environment map.
+    pub fn parse(spec: &str) -> std::result::Result<Self, String> {
+        match spec {
+            "sky" => return Ok(Self::sky()),
+            "black" => return Ok(Self::Solid(Color::new(0.0, 0.0, 0.0))),
+            "white" => return Ok(Self::Solid(Color::new(1.0, 1.0, 1.0))),
+            _ => {}
+        }
+
+        if let Some(gradient) = spec.strip_prefix("gradient:") {
+            let parts: Vec<Option<Vec3>> = gradient.split(':').map(parse_triple).collect();
+            return match parts[..] {
+                [Some(bottom), Some(top)] => Ok(Self::Gradient { bottom, top, up: Vec3::new(0.0, 1.0, 0.0) }),
+                [Some(bottom), Some(top), Some(up)] if !up.near_zero() => Ok(Self::Gradient { bottom, top, up }),
+                _ => Err(format!("invalid background `{}`: expected gradient:r,g,b:r,g,b with an optional \
+                                  non-zero :x,y,z up axis", spec)),
+            }
+        }
+
+        if spec.ends_with(".hdr") {
+            let map = EnvironmentMap::load(Path::new(spec), 1.0).map_err(|e| e.to_string())?;
+            return Ok(Self::Environment(Arc::new(map)))
+        }
+
+        match parse_triple(spec) {
+            Some(color) => Ok(Self::Solid(color)),
+            None => Err(format!("invalid background `{}`: expected sky, black, white, r,g,b, \
+                                 gradient:r,g,b:r,g,b or an .hdr file", spec)),
+        }
+    }
+}
+
+/// Three comma separated numbers.
+fn parse_triple(s: &str) -> Option<Vec3> {
+    let numbers = s.split(',').map(|c| c.trim().parse::<f64>()).collect::<std::result::Result<Vec<_>, _>>();
+    match numbers.as_deref() {
+        Ok([x, y, z]) => Some(Vec3::new(*x, *y, *z)),
+        _ => None,
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::sky()
+    }
+}
+
+/// An equirectangular (latitude/longitude) environment map with +y up. The
+/// centre of the image looks down -z, the usual convention for HDRI skies.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    image: HdrImage,
+    intensity: f64,
+}
+
+impl EnvironmentMap {
+    pub fn load(path: &Path, intensity: f64) -> Result<Self> {
+        Ok(EnvironmentMap { image: load_hdr(path)?, intensity })
+    }
+
+    /// Bilinearly filtered radiance arriving from direction `dir`.
+    pub fn lookup(&self, dir: &Vec3) -> Color {
+        let d = dir.unit_vector();
+        let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+        let v = clamp(d.y, -1.0, 1.0).acos() / PI;
+
+        let (w, h) = (self.image.width, self.image.height);
+        let x = u * w as f64 - 0.5;
+        let y = clamp(v * h as f64 - 0.5, 0.0, (h - 1) as f64);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        // Longitude wraps around; latitude is clamped at the poles.
+        let x0 = (x0 as isize).rem_euclid(w as isize) as usize;
+        let x1 = (x0 + 1) % w;
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(h - 1);
+
+        let px = |x: usize, y: usize| self.image.pixels[y * w + x];
+        let top = px(x0, y0) * (1.0 - fx) + px(x1, y0) * fx;
+        let bottom = px(x0, y1) * (1.0 - fx) + px(x1, y1) * fx;
+        (top * (1.0 - fy) + bottom * fy) * self.intensity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Point;
+
+    #[test]
+    fn parses_every_kind_of_background() {
+        assert!(matches!(Background::parse("sky"), Ok(Background::Gradient { .. })));
+        match Background::parse("0.1, 0.2,0.3") {
+            Ok(Background::Solid(color)) => assert_eq!(color, Color::new(0.1, 0.2, 0.3)),
+            other => panic!("{:?}", other),
+        }
+        match Background::parse("gradient:0,0,0:1,1,1") {
+            Ok(Background::Gradient { bottom, top, up }) => {
+                assert_eq!((bottom, top, up), (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0), Vec3::Y_HAT));
+            }
+            other => panic!("{:?}", other),
+        }
+        let gradient = Background::parse("gradient:1,0,0:0,0,1:0,0,2").unwrap();
+        let along = |z: f64| gradient.color(&Ray::new(Point::ORIGIN, Vec3::new(0.0, 0.0, z)));
+        assert_eq!(along(1.0), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(along(-3.0), Color::new(1.0, 0.0, 0.0));
+
+        for bad in ["grey", "1,2", "1,2,3,4", "gradient:1,1,1", "gradient:1,1,1:0,0,0:0,0,0", "gradient:a:b"] {
+            let message = Background::parse(bad).unwrap_err();
+            assert!(message.starts_with(&format!("invalid background `{}`", bad)), "{}", message);
+        }
+    }
+
+    #[test]
+    fn environment_directions_map_to_latitude_and_longitude() {
+        // Each pixel's red is its column squared and its green its row, so
+        // where a lookup lands can be read back off the color.
+        let (width, height) = (4, 2);
+        let pixels = (0..width * height).map(|i| {
+            let (x, y) = (i % width, i / width);
+            Color::new((x * x) as f64, y as f64, 0.0)
+        });
+        let image = HdrImage { width, height, pixels: pixels.collect() };
+        let map = EnvironmentMap { image, intensity: 2.0 };
+        let lookup = |x: f64, y: f64, z: f64| map.lookup(&Vec3::new(x, y, z)) / 2.0;
+
+        // -z is the middle of the image, between columns 1 and 2 and rows 0 and 1.
+        assert_eq!(lookup(0.0, 0.0, -3.0), Color::new(2.5, 0.5, 0.0));
+        // Quarter turns go a quarter of the way across, and +z straddles the
+        // seam where the longitude wraps around.
+        assert_eq!(lookup(1.0, 0.0, 0.0), Color::new(6.5, 0.5, 0.0));
+        assert_eq!(lookup(-1.0, 0.0, 0.0), Color::new(0.5, 0.5, 0.0));
+        assert_eq!(lookup(0.0, 0.0, 1.0), Color::new(4.5, 0.5, 0.0));
+        // Straight up and down read the top and bottom rows.
+        assert_eq!(lookup(0.0, 1.0, -1.0e-12).y, 0.0);
+        assert_eq!(lookup(0.0, -1.0, -1.0e-12).y, 1.0);
+    }
+}
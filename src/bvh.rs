@@ -0,0 +1,408 @@
+use std::marker::PhantomData;
+
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HitList, HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::vec::Vec3;
+
+/// Number of buckets centroids are binned into when evaluating the surface
+/// area heuristic. Twelve is plenty; more bins barely improve the tree.
+const SAH_BINS: usize = 12;
+/// Leaves are allowed to hold this many primitives when splitting isn't worth it.
+const MAX_LEAF_PRIMS: usize = 4;
+/// Cost of one node traversal relative to one primitive intersection.
+const TRAVERSAL_COST: f64 = 0.5;
+/// Past this depth we stop trusting the SAH and split at the median so that the
+/// fixed-size traversal stack can never overflow.
+const MAX_SAH_DEPTH: usize = 48;
+const STACK_SIZE: usize = 128;
+
+#[derive(Clone, Copy, Debug)]
+enum BvhNode {
+    Leaf { bbox: Aabb, first: usize, count: usize },
+    /// The first child immediately follows its parent in the node array, so only
+    /// the index of the second child needs storing.
+    Interior { bbox: Aabb, second: usize, axis: usize },
+}
+
+/// A bounding volume hierarchy over a set of hittables. The tree is built once
+/// with a binned surface area heuristic and flattened into a depth-first array,
+/// so a ray only has to look at the handful of primitives whose boxes it actually
+/// passes through instead of every object in the scene.
+///
+/// Objects with no bounding box (see `Hit::bounding_box`) can't be sorted into
+/// the tree and are simply tested against every ray, as in a `HitList`.
+#[derive(Clone, Debug)]
+pub struct Bvh<Mat, P = Hittable<Mat>>
+where
+    Mat: Material + Clone + Default,
+{
+    nodes: Vec<BvhNode>,
+    prims: Vec<P>,
+    unbounded: Vec<P>,
+    _material: PhantomData<Mat>,
+}
+
+/// An empty tree, which nothing hits.
+impl<Mat, P> Default for Bvh<Mat, P>
+where
+    Mat: Material + Clone + Default,
+{
+    fn default() -> Self {
+        Bvh { nodes: Vec::new(), prims: Vec::new(), unbounded: Vec::new(), _material: PhantomData }
+    }
+}
+
+/// What the builder needs to know about a primitive, so the primitives
+/// themselves are only moved once at the very end.
+struct PrimRef {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point,
+}
+
+impl<Mat, P> Bvh<Mat, P>
+where
+    Mat: Material + Clone + Default,
+    P: Hit<Mat>,
+{
+    pub fn new(objects: Vec<P>) -> Self {
+        Self::with_boxes(objects, |obj| obj.bounding_box())
+    }
+}
+
+impl<Mat, P> Bvh<Mat, P>
+where
+    Mat: Material + Clone + Default,
+{
+    /// A tree over primitives that can't be hit on their own, like the faces of
+    /// a mesh, which need the mesh's buffers. `bbox` gives each one's box and
+    /// `traverse` does the intersecting.
+    pub(crate) fn with_boxes<F>(objects: Vec<P>, bbox: F) -> Self
+    where
+        F: Fn(&P) -> Option<Aabb>,
+    {
+        let mut refs = Vec::with_capacity(objects.len());
+        let mut bounded = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for obj in objects {
+            match bbox(&obj) {
+                Some(bbox) => {
+                    refs.push(PrimRef { index: bounded.len(), bbox, centroid: bbox.centroid() });
+                    bounded.push(obj);
+                }
+                None => unbounded.push(obj),
+            }
+        }
+
+        let mut nodes = Vec::with_capacity(2 * refs.len());
+        let mut order = Vec::with_capacity(refs.len());
+        if !refs.is_empty() {
+            build(&mut refs, 0, &mut nodes, &mut order);
+        }
+
+        // Lay the primitives out in leaf order so each leaf is a contiguous slice.
+        let mut slots: Vec<Option<P>> = bounded.into_iter().map(Some).collect();
+        let prims = order.iter().map(|&i| slots[i].take().unwrap()).collect();
+
+        Bvh { nodes, prims, unbounded, _material: PhantomData }
+    }
+
+    /// Every primitive in the tree, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &P> {
+        self.prims.iter().chain(self.unbounded.iter())
+    }
+
+    #[allow(unused)]
+    pub fn len(&self) -> usize {
+        self.prims.len() + self.unbounded.len()
+    }
+
+    #[allow(unused)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Box around everything in the tree, as in `Hit::bounding_box`.
+    pub(crate) fn bounds(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() { return None }
+        self.nodes.first().map(|node| match *node {
+            BvhNode::Leaf { bbox, .. } | BvhNode::Interior { bbox, .. } => bbox,
+        })
+    }
+
+    /// Finds the closest hit along `ray` among the primitives whose boxes it
+    /// passes through, by calling `hit_prim` with each of them and the nearest
+    /// `t` found so far; it works like `Hit::hit` with that as `t_max`.
+    pub(crate) fn traverse<'a, F>(&'a self,
+                                  ray: &Ray,
+                                  t_min: f64,
+                                  t_max: f64,
+                                  rec: &mut HitRecord<'a, Mat>,
+                                  mut hit_prim: F) -> bool
+    where
+        F: FnMut(&'a P, f64, &mut HitRecord<'a, Mat>) -> bool,
+    {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+
+        for obj in self.unbounded.iter() {
+            if hit_prim(obj, closest_so_far, rec) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+
+        if self.nodes.is_empty() { return hit_anything }
+
+        let inv_dir = Vec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+        let mut stack = [0usize; STACK_SIZE];
+        let mut top = 0;
+        let mut current = 0;
+
+        loop {
+            match self.nodes[current] {
+                BvhNode::Leaf { bbox, first, count } => {
+                    if bbox.hit_inv(&ray.origin, &inv_dir, t_min, closest_so_far) {
+                        for obj in self.prims[first..first + count].iter() {
+                            if hit_prim(obj, closest_so_far, rec) {
+                                hit_anything = true;
+                                closest_so_far = rec.t;
+                            }
+                        }
+                    }
+                }
+                BvhNode::Interior { bbox, second, axis } => {
+                    if bbox.hit_inv(&ray.origin, &inv_dir, t_min, closest_so_far) {
+                        // Visit the child nearer the ray origin first so that
+                        // `closest_so_far` shrinks as early as possible.
+                        if dir_is_neg[axis] {
+                            stack[top] = current + 1;
+                            current = second;
+                        } else {
+                            stack[top] = second;
+                            current += 1;
+                        }
+                        top += 1;
+                        continue;
+                    }
+                }
+            }
+            if top == 0 { break }
+            top -= 1;
+            current = stack[top];
+        }
+
+        hit_anything
+    }
+}
+
+impl<Mat> From<HitList<Mat>> for Bvh<Mat>
+where
+    Mat: Material + Clone + Default,
+{
+    fn from(list: HitList<Mat>) -> Self {
+        Bvh::new(list.into_inner())
+    }
+}
+
+/// Recursively builds the subtree over `refs`, appending nodes depth first and
+/// recording leaf primitive order. Returns the index of the subtree's root.
+fn build(refs: &mut [PrimRef], depth: usize, nodes: &mut Vec<BvhNode>, order: &mut Vec<usize>) -> usize {
+    let node = nodes.len();
+    let n = refs.len();
+    let bbox = refs.iter().fold(Aabb::EMPTY, |acc, r| acc.surrounding(&r.bbox));
+
+    let centroid_bounds = refs.iter().fold(Aabb::EMPTY, |acc, r| acc.grow(&r.centroid));
+    let axis = centroid_bounds.extent().max_dimension();
+    let lo = centroid_bounds.min.component(axis);
+    let span = centroid_bounds.extent().component(axis);
+
+    if n == 1 || (n <= MAX_LEAF_PRIMS && span <= 0.0) {
+        return leaf(refs, bbox, nodes, order);
+    }
+
+    let mid = if span <= 0.0 || depth >= MAX_SAH_DEPTH {
+        median_split(refs, axis)
+    } else {
+        let bin_of = |c: &Point| (((c.component(axis) - lo) / span * SAH_BINS as f64) as usize).min(SAH_BINS - 1);
+
+        let mut counts = [0usize; SAH_BINS];
+        let mut bounds = [Aabb::EMPTY; SAH_BINS];
+        for r in refs.iter() {
+            let b = bin_of(&r.centroid);
+            counts[b] += 1;
+            bounds[b] = bounds[b].surrounding(&r.bbox);
+        }
+
+        // Sweep from the right to get the cost of everything above each split,
+        // then from the left to combine it with everything below.
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0usize; SAH_BINS];
+        let mut acc = Aabb::EMPTY;
+        let mut count = 0;
+        for b in (1..SAH_BINS).rev() {
+            acc = acc.surrounding(&bounds[b]);
+            count += counts[b];
+            right_area[b] = acc.surface_area();
+            right_count[b] = count;
+        }
+
+        let parent_area = bbox.surface_area().max(f64::MIN_POSITIVE);
+        let mut best_cost = f64::INFINITY;
+        let mut best_split = 0;
+        let mut acc = Aabb::EMPTY;
+        let mut count = 0;
+        for b in 0..SAH_BINS - 1 {
+            acc = acc.surrounding(&bounds[b]);
+            count += counts[b];
+            if count == 0 || right_count[b + 1] == 0 { continue }
+            let cost = TRAVERSAL_COST
+                + (count as f64 * acc.surface_area() + right_count[b + 1] as f64 * right_area[b + 1]) / parent_area;
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = b;
+            }
+        }
+
+        if n <= MAX_LEAF_PRIMS && best_cost >= n as f64 {
+            return leaf(refs, bbox, nodes, order);
+        }
+
+        let mut mid = 0;
+        for i in 0..n {
+            if bin_of(&refs[i].centroid) <= best_split {
+                refs.swap(i, mid);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == n { median_split(refs, axis) } else { mid }
+    };
+
+    nodes.push(BvhNode::Interior { bbox, second: 0, axis });
+    build(&mut refs[..mid], depth + 1, nodes, order);
+    let second = build(&mut refs[mid..], depth + 1, nodes, order);
+    nodes[node] = BvhNode::Interior { bbox, second, axis };
+    node
+}
+
+fn leaf(refs: &[PrimRef], bbox: Aabb, nodes: &mut Vec<BvhNode>, order: &mut Vec<usize>) -> usize {
+    let first = order.len();
+    order.extend(refs.iter().map(|r| r.index));
+    nodes.push(BvhNode::Leaf { bbox, first, count: refs.len() });
+    nodes.len() - 1
+}
+
+fn median_split(refs: &mut [PrimRef], axis: usize) -> usize {
+    let mid = refs.len() / 2;
+    refs.select_nth_unstable_by(mid, |a, b| {
+        a.centroid.component(axis).total_cmp(&b.centroid.component(axis))
+    });
+    mid
+}
+
+impl<Mat, P> Hit<Mat> for Bvh<Mat, P>
+where
+    Mat: Material + Clone + Default,
+    P: Hit<Mat>,
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a, Mat>) -> bool {
+        self.traverse(ray, t_min, t_max, rec, |obj, closest_so_far, rec| obj.hit(ray, t_min, closest_so_far, rec))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, MatKind};
+    use crate::moving_sphere::MovingSphere;
+    use crate::render::Color;
+    use crate::sphere::Sphere;
+
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+
+    fn random_point(rng: &mut StdRng, min: f64, max: f64) -> Point {
+        Point::new(rng.gen_range(min..max), rng.gen_range(min..max), rng.gen_range(min..max))
+    }
+
+    #[test]
+    fn bvh_hits_match_linear_list() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mat = MatKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+
+        let mut list: HitList<MatKind> = HitList::new();
+        list.push(Hittable::Sphere(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, mat.clone())));
+        for _ in 0..1000 {
+            let center = random_point(&mut rng, -10.0, 10.0);
+            list.push(Hittable::Sphere(Sphere::new(center, rng.gen_range(0.05..0.8), mat.clone())));
+        }
+        let bvh = Bvh::from(list.clone());
+        assert_eq!(bvh.len(), list.len());
+
+        let mut hits = 0;
+        for _ in 0..20_000 {
+            let origin = random_point(&mut rng, -15.0, 15.0);
+            let dir = random_point(&mut rng, -1.0, 1.0);
+            let ray = Ray::new(origin, dir);
+
+            let mut list_rec = HitRecord::empty();
+            let mut bvh_rec = HitRecord::empty();
+            let list_hit = list.hit(&ray, 0.001, f64::INFINITY, &mut list_rec);
+            let bvh_hit = bvh.hit(&ray, 0.001, f64::INFINITY, &mut bvh_rec);
+
+            assert_eq!(list_hit, bvh_hit);
+            if list_hit {
+                hits += 1;
+                assert_eq!(list_rec.t, bvh_rec.t);
+                assert_eq!(list_rec.p, bvh_rec.p);
+                assert_eq!(list_rec.normal, bvh_rec.normal);
+                assert_eq!(list_rec.front_face, bvh_rec.front_face);
+            }
+        }
+        // Make sure the comparison actually exercised the tree.
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn bvh_finds_moving_spheres_at_any_time() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mat = MatKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+
+        let mut list: HitList<MatKind> = HitList::new();
+        for _ in 0..500 {
+            let center0 = random_point(&mut rng, -10.0, 10.0);
+            let center1 = center0 + random_point(&mut rng, -2.0, 2.0);
+            let sphere = MovingSphere::new(center0, center1, 0.0, 1.0, rng.gen_range(0.05..0.5), mat.clone());
+            list.push(Hittable::MovingSphere(sphere));
+        }
+        let bvh = Bvh::from(list.clone());
+
+        let mut hits = 0;
+        for _ in 0..20_000 {
+            let origin = random_point(&mut rng, -15.0, 15.0);
+            let dir = random_point(&mut rng, -1.0, 1.0);
+            let ray = Ray::with_time(origin, dir, rng.gen_range(0.0..1.0));
+
+            let mut list_rec = HitRecord::empty();
+            let mut bvh_rec = HitRecord::empty();
+            let list_hit = list.hit(&ray, 0.001, f64::INFINITY, &mut list_rec);
+            let bvh_hit = bvh.hit(&ray, 0.001, f64::INFINITY, &mut bvh_rec);
+
+            assert_eq!(list_hit, bvh_hit);
+            if list_hit {
+                hits += 1;
+                assert_eq!(list_rec.t, bvh_rec.t);
+                assert_eq!(list_rec.p, bvh_rec.p);
+            }
+        }
+        assert!(hits > 1000);
+    }
+}
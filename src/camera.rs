@@ -1,11 +1,9 @@
-use rand::prelude::*;
+use rand::Rng;
 
 use crate::ray::Ray;
-use crate::render::{degrees_to_radians, Point};
+use crate::render::{degrees_to_radians, random_f64, Point};
 use crate::vec::Vec3;
 
-use rayon::prelude::*;
-
 #[derive(Debug)]
 pub struct Camera {
     origin: Point,
@@ -15,7 +13,9 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
-    rng: ThreadRng,
+    /// Shutter open and close times; rays are spread evenly between them.
+    time0: f64,
+    time1: f64,
 }
 
 impl Camera {
@@ -45,8 +45,6 @@ impl Camera {
 
         let lens_radius = aperture / 2.0;
 
-        let rng = thread_rng();
-
         Camera { 
             origin,
             horizontal,
@@ -55,15 +53,24 @@ impl Camera {
             u,
             v,
             lens_radius,
-            rng,
+            time0: 0.0,
+            time1: 0.0,
         }
     }
 
-    pub fn get_ray(&mut self, s: f64, t: f64) -> Ray {
-        let rd = Vec3::random_in_unit_disk(&mut self.rng) * self.lens_radius;
+    /// Keeps the shutter open from `time0` to `time1`, for motion blur. By
+    /// default it opens and closes at 0.
+    pub fn with_shutter(self, time0: f64, time1: f64) -> Self {
+        Camera { time0, time1, ..self }
+    }
+
+    pub fn get_ray<R: Rng + ?Sized>(&self, s: f64, t: f64, rng: &mut R) -> Ray {
+        let rd = Vec3::random_in_unit_disk(rng) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
-        Ray::new(
+        let time = if self.time1 > self.time0 { random_f64(self.time0, self.time1, rng) } else { self.time0 };
+        Ray::with_time(
             self.origin + offset,
-            self.ll_corner + self.horizontal * s + self.vertical * t - self.origin - offset)
+            self.ll_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+            time)
     }
 }
\ No newline at end of file
@@ -0,0 +1,312 @@
+use crate::aabb::Aabb;
+use crate::hit::{step_past, Hit, HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsgOp {
+    /// Inside either child.
+    Union,
+    /// Inside both children.
+    Intersection,
+    /// Inside the left child but not the right one.
+    Difference,
+}
+
+impl CsgOp {
+    #[inline]
+    fn inside(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            Self::Union => in_left || in_right,
+            Self::Intersection => in_left && in_right,
+            Self::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry: the solid `op` makes of two others, like a
+/// sphere with a bite taken out of it (a difference) or a lens (the
+/// intersection of two spheres).
+///
+/// The children have to be closed solids, so that whether a ray is inside one
+/// can be told from which side it hits the next surface of it: spheres, boxes,
+/// cylinders, cones, tori, closed meshes, other CSG nodes and transforms of
+/// any of those. Each surface of the result keeps the material of the child
+/// it came from.
+#[derive(Clone, Debug)]
+pub struct Csg<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub op: CsgOp,
+    pub left: Box<Hittable<Mat>>,
+    pub right: Box<Hittable<Mat>>,
+}
+
+impl<Mat> Csg<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(op: CsgOp, left: Hittable<Mat>, right: Hittable<Mat>) -> Self {
+        Csg { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    /// Walks the surfaces of both children in order, keeping track of whether
+    /// the ray is inside each, and hands each place where it goes in or out of
+    /// the result to `found` until that returns false. Each child is only
+    /// asked for its next surface once the walk gets there, so finding the
+    /// closest boundary costs a hit or two per child rather than all of them.
+    fn boundaries<'a, F>(&'a self, ray: &Ray, t_min: f64, t_max: f64, mut found: F)
+    where
+        F: FnMut(HitRecord<'a, Mat>) -> bool,
+    {
+        // Whether the ray starts inside a child depends on its next surface,
+        // even one past `t_max`, so children are looked at all the way along.
+        let mut left = Crossings::new(&self.left, ray, t_min);
+        let mut right = Crossings::new(&self.right, ray, t_min);
+        let mut in_left = left.starts_inside();
+        let mut in_right = right.starts_inside();
+        let mut inside = self.op.inside(in_left, in_right);
+
+        loop {
+            let from_left = match (&left.next, &right.next) {
+                (Some(l), Some(r)) => l.t <= r.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let (child, in_child) = if from_left { (&mut left, &mut in_left) } else { (&mut right, &mut in_right) };
+            let rec = match child.next {
+                Some(rec) if rec.t <= t_max => rec,
+                _ => break,
+            };
+            child.advance(ray);
+            let entering = rec.front_face != Some(false);
+            *in_child = entering;
+
+            let now = self.op.inside(in_left, in_right);
+            if now != inside {
+                inside = now;
+                // Which side of the result the ray hit follows from whether it
+                // is now inside it. A surface of the right child bounding a
+                // difference faces the other way than it did on the child,
+                // but the normal already faces the ray either way. The
+                // tangent frame belongs on the outside of the result, so it
+                // turns over along with the outside.
+                let mut rec = rec;
+                if entering != now { rec.bitangent = -rec.bitangent; }
+                rec.front_face = Some(now);
+                if !found(rec) { break }
+            }
+        }
+    }
+}
+
+/// The surfaces of one child of a CSG node along a ray, found one at a time.
+struct Crossings<'a, Mat>
+where
+    Mat: Material + Clone + Default
+{
+    child: &'a Hittable<Mat>,
+    /// The next surface the ray crosses, if there is one.
+    next: Option<HitRecord<'a, Mat>>,
+}
+
+impl<'a, Mat> Crossings<'a, Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn new(child: &'a Hittable<Mat>, ray: &Ray, t_min: f64) -> Self {
+        let mut crossings = Crossings { child, next: None };
+        crossings.find(ray, t_min);
+        crossings
+    }
+
+    /// A closed solid is left through its back faces, so a ray whose first
+    /// surface is one of those started out inside.
+    fn starts_inside(&self) -> bool {
+        self.next.is_some_and(|rec| rec.front_face == Some(false))
+    }
+
+    fn find(&mut self, ray: &Ray, t_min: f64) {
+        let mut rec = HitRecord::empty();
+        self.next = self.child.hit(ray, t_min, f64::INFINITY, &mut rec).then_some(rec);
+    }
+
+    /// Moves on to the surface after `next`.
+    fn advance(&mut self, ray: &Ray) {
+        if let Some(rec) = self.next {
+            self.find(ray, step_past(rec.t));
+        }
+    }
+}
+
+impl<Mat> Hit<Mat> for Csg<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a, Mat>) -> bool {
+        let mut hit = false;
+        self.boundaries(ray, t_min, t_max, |boundary| {
+            (*rec, hit) = (boundary, true);
+            false
+        });
+        hit
+    }
+
+    fn hits<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'a, Mat>> {
+        let mut hits = Vec::new();
+        self.boundaries(ray, t_min, t_max, |boundary| {
+            hits.push(boundary);
+            true
+        });
+        hits
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounding_box(), self.right.bounding_box());
+        match self.op {
+            CsgOp::Union => Some(left?.surrounding(&right?)),
+            // Nothing can stick out of both children, or out of the left one
+            // in a difference.
+            CsgOp::Intersection => match (left, right) {
+                (Some(l), Some(r)) => Some(Aabb::new(l.min.max(&r.min), l.max.min(&r.max))),
+                (l, r) => l.or(r),
+            },
+            CsgOp::Difference => left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::material::MatKind;
+    use crate::mesh::{Mesh, MeshData, MeshFace};
+    use crate::render::Point;
+    use crate::sphere::Sphere;
+    use crate::vec::Vec3;
+
+    const EPSILON: f64 = 1.0e-9;
+
+    fn sphere(x: f64, radius: f64) -> Hittable<MatKind> {
+        Hittable::Sphere(Sphere::new(Point::new(x, 0.0, 0.0), radius, MatKind::default()))
+    }
+
+    /// The two unit spheres at x = -0.5 and x = 0.5.
+    fn pair(op: CsgOp) -> Csg<MatKind> {
+        Csg::new(op, sphere(-0.5, 1.0), sphere(0.5, 1.0))
+    }
+
+    /// Where along x `ray` crosses the surface of `csg`, and whether it goes
+    /// in there. Also checks that `hit` finds the first of them.
+    fn crossings(csg: &Csg<MatKind>, ray: &Ray) -> Vec<(f64, bool)> {
+        let hits = csg.hits(ray, 0.0, f64::INFINITY);
+        let mut rec = HitRecord::empty();
+        assert_eq!(csg.hit(ray, 0.0, f64::INFINITY, &mut rec), !hits.is_empty());
+        if let Some(first) = hits.first() {
+            assert!((rec.t - first.t).abs() < EPSILON && rec.front_face == first.front_face);
+        }
+        for rec in hits.iter() {
+            // The normal always faces the ray.
+            assert!(rec.normal.dot(&ray.dir) < 0.0, "{:?}", rec);
+        }
+        hits.iter().map(|rec| (rec.p.x, rec.front_face == Some(true))).collect()
+    }
+
+    fn assert_crossings(csg: &Csg<MatKind>, ray: &Ray, expected: &[(f64, bool)]) {
+        let found = crossings(csg, ray);
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for ((x, entering), (want_x, want_entering)) in found.iter().zip(expected) {
+            assert!((x - want_x).abs() < 1.0e-6 && entering == want_entering, "{:?}", found);
+        }
+    }
+
+    #[test]
+    fn union_intersection_and_difference() {
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::X_HAT);
+        assert_crossings(&pair(CsgOp::Union), &ray, &[(-1.5, true), (1.5, false)]);
+        assert_crossings(&pair(CsgOp::Intersection), &ray, &[(-0.5, true), (0.5, false)]);
+        assert_crossings(&pair(CsgOp::Difference), &ray, &[(-1.5, true), (-0.5, false)]);
+        // From the other side the difference is reached through the right
+        // sphere's surface, which keeps that sphere's material.
+        let difference = pair(CsgOp::Difference);
+        let ray = Ray::new(Point::new(5.0, 0.0, 0.0), -Vec3::X_HAT);
+        assert_crossings(&difference, &ray, &[(-0.5, true), (-1.5, false)]);
+        let mut rec = HitRecord::empty();
+        assert!(difference.hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        let right = match &*difference.right { Hittable::Sphere(s) => s, _ => unreachable!() };
+        assert!(std::ptr::eq(rec.material(), &right.material));
+        assert!((rec.outward_normal() - Vec3::X_HAT).near_zero());
+        // Rays that miss the result miss, even through one of the children.
+        let ray = Ray::new(Point::new(1.2, 0.0, -5.0), Vec3::Z_HAT);
+        assert_crossings(&pair(CsgOp::Intersection), &ray, &[]);
+        assert_crossings(&difference, &ray, &[]);
+    }
+
+    #[test]
+    fn rays_that_start_inside() {
+        let ray = Ray::new(Point::ORIGIN, Vec3::X_HAT);
+        assert_crossings(&pair(CsgOp::Union), &ray, &[(1.5, false)]);
+        assert_crossings(&pair(CsgOp::Intersection), &ray, &[(0.5, false)]);
+        // Inside the right sphere, so outside the difference.
+        assert_crossings(&pair(CsgOp::Difference), &ray, &[]);
+        let ray = Ray::new(Point::ORIGIN, -Vec3::X_HAT);
+        assert_crossings(&pair(CsgOp::Difference), &ray, &[(-0.5, true), (-1.5, false)]);
+        let ray = Ray::new(Point::new(-1.0, 0.0, 0.0), Vec3::X_HAT);
+        assert_crossings(&pair(CsgOp::Difference), &ray, &[(-0.5, false)]);
+    }
+
+    #[test]
+    fn nested_operations() {
+        // Both spheres with a hollow in the middle, then cut in half.
+        let hollow = Csg::new(CsgOp::Difference, Hittable::Csg(pair(CsgOp::Union)), sphere(0.0, 0.25));
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::X_HAT);
+        assert_crossings(&hollow, &ray, &[(-1.5, true), (-0.25, false), (0.25, true), (1.5, false)]);
+        let ray = Ray::new(Point::ORIGIN, Vec3::X_HAT);
+        assert_crossings(&hollow, &ray, &[(0.25, true), (1.5, false)]);
+
+        let half = Csg::new(CsgOp::Intersection, Hittable::Csg(hollow), sphere(1.0, 1.0));
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::X_HAT);
+        assert_crossings(&half, &ray, &[(0.25, true), (1.5, false)]);
+        let ray = Ray::new(Point::new(5.0, 0.0, 0.0), -Vec3::X_HAT);
+        assert_crossings(&half, &ray, &[(1.5, true), (0.25, false)]);
+    }
+
+    /// A mesh of `count` small octahedra strung along the x axis, one per unit.
+    fn octahedra(count: usize) -> Hittable<MatKind> {
+        let mut data = MeshData::default();
+        let mut faces = Vec::new();
+        for i in 0..count {
+            let center = Point::new(i as f64, 0.0, 0.0);
+            let base = data.positions.len();
+            for axis in [Vec3::X_HAT, Vec3::Y_HAT, Vec3::Z_HAT] {
+                data.positions.push(center + axis * 0.4);
+                data.positions.push(center - axis * 0.4);
+            }
+            for (x, y, z) in (0..8).map(|s| (s & 1, 2 + (s >> 1 & 1), 4 + (s >> 2 & 1))) {
+                let corners = [base + x, base + y, base + z];
+                let [a, b, c] = corners.map(|i| data.positions[i]);
+                // Wound to face outward.
+                let outward = (b - a).cross(&(c - a)).dot(&(a - center)) > 0.0;
+                let positions = if outward { corners } else { [corners[0], corners[2], corners[1]] };
+                faces.push(MeshFace::new(positions, None, None));
+            }
+        }
+        Hittable::Mesh(Mesh::new(Arc::new(data), faces, MatKind::default()))
+    }
+
+    #[test]
+    fn children_with_many_surfaces() {
+        // 80 surfaces along the ray, and the one that matters is far down it.
+        let ray = Ray::new(Point::new(-5.0, 0.05, 0.05), Vec3::X_HAT);
+        let intersection = Csg::new(CsgOp::Intersection, octahedra(40), sphere(36.0, 0.5));
+        assert_crossings(&intersection, &ray, &[(35.7, true), (36.3, false)]);
+        let difference = Csg::new(CsgOp::Difference, sphere(36.0, 0.5), octahedra(40));
+        let sphere_x = (0.25_f64 - 0.005).sqrt();
+        let expected = [(36.0 - sphere_x, true), (35.7, false), (36.3, true), (36.0 + sphere_x, false)];
+        assert_crossings(&difference, &ray, &expected);
+    }
+}
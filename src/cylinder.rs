@@ -0,0 +1,272 @@
+use crate::aabb::Aabb;
+use crate::frame::Frame;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::plane::{disk_box, disk_derivatives, polar_angle};
+use crate::ray::Ray;
+use crate::render::{Point, PI};
+use crate::triangle::BOX_PADDING;
+use crate::vec::Vec3;
+
+/// The closest hit found so far while trying a shape's parts one by one: ray
+/// parameter, normal in the shape's frame, texture coordinates and how the
+/// point moves with them, also in the shape's frame.
+type LocalHit = (f64, Vec3, (f64, f64), (Vec3, Vec3));
+
+/// Keeps `candidate` if it is nearer than whatever `closest` holds.
+#[inline]
+fn keep_closest(closest: &mut Option<LocalHit>, candidate: LocalHit) {
+    if closest.is_none_or(|(t, ..)| candidate.0 < t) {
+        *closest = Some(candidate);
+    }
+}
+
+/// Where a ray (in the shape's frame) crosses the cap of `radius` at height
+/// `z`, which faces along `normal_z`.
+#[inline]
+fn hit_cap(o: &Vec3, d: &Vec3, z: f64, normal_z: f64, radius: f64, t_min: f64, t_max: f64) -> Option<LocalHit> {
+    let t = (z - o.z) / d.z;
+    if !(t_min..=t_max).contains(&t) { return None }
+    let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+    let r = (x * x + y * y).sqrt();
+    if r > radius { return None }
+    Some((t, Vec3::new(0.0, 0.0, normal_z), (polar_angle(x, y), r / radius), disk_derivatives(x, y, radius)))
+}
+
+/// Roots of `a t^2 + b t + c` in increasing order.
+#[inline]
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1.0e-12 {
+        if b.abs() < 1.0e-12 { return None }
+        return Some((-c / b, -c / b))
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 { return None }
+    // The numerically stable form, avoiding cancellation in -b +- sqrt.
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = (q / a, if q != 0.0 { c / q } else { q / a });
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+fn finish_hit<'a, Mat>(frame: &Frame, ray: &Ray, hit: LocalHit, material: &'a Mat, hit_record: &mut HitRecord<'a, Mat>)
+where
+    Mat: Material + Clone + Default
+{
+    let (t, normal, uv, (dpdu, dpdv)) = hit;
+    hit_record.t = t;
+    hit_record.p = ray.at(t);
+    hit_record.set_face_normal(ray, &frame.vector_to_world(&normal).unit_vector());
+    hit_record.set_tangents(&frame.vector_to_world(&dpdu), &frame.vector_to_world(&dpdv));
+    (hit_record.u, hit_record.v) = uv;
+    hit_record.material = Some(material);
+}
+
+/// A solid cylinder of `radius` from `base` to `top`, closed by a disk at
+/// each end. On the side `u` goes around the axis and `v` from base to top;
+/// on the caps they are as on a `Disk`.
+#[derive(Clone, Debug)]
+pub struct Cylinder<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    frame: Frame,
+    pub height: f64,
+    pub radius: f64,
+    pub material: Mat,
+}
+
+impl<Mat> Cylinder<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(base: Point, top: Point, radius: f64, material: Mat) -> Self {
+        Cylinder { frame: Frame::new(base, top - base), height: (top - base).length(), radius, material }
+    }
+}
+
+impl<Mat> Hit<Mat> for Cylinder<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let o = self.frame.to_local(&ray.origin);
+        let d = self.frame.vector_to_local(&ray.dir);
+        let (h, r) = (self.height, self.radius);
+        let mut closest = None;
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - r * r;
+        if a > 0.0 {
+            if let Some((t0, t1)) = solve_quadratic(a, b, c) {
+                for t in [t0, t1] {
+                    let z = o.z + t * d.z;
+                    if (t_min..=t_max).contains(&t) && (0.0..=h).contains(&z) {
+                        let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+                        let normal = Vec3::new(x / r, y / r, 0.0);
+                        let derivatives = (Vec3::new(-y, x, 0.0) * (2.0 * PI), Vec3::new(0.0, 0.0, h));
+                        keep_closest(&mut closest, (t, normal, (polar_angle(x, y), z / h), derivatives));
+                        break
+                    }
+                }
+            }
+        }
+        for (z, normal_z) in [(0.0, -1.0), (h, 1.0)] {
+            if let Some(hit) = hit_cap(&o, &d, z, normal_z, r, t_min, t_max) {
+                keep_closest(&mut closest, hit);
+            }
+        }
+
+        match closest {
+            Some(hit) => { finish_hit(&self.frame, ray, hit, &self.material, hit_record); true }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let top = self.frame.to_world(&Vec3::new(0.0, 0.0, self.height));
+        let base = disk_box(self.frame.origin, &self.frame.w, self.radius);
+        Some(base.surrounding(&disk_box(top, &self.frame.w, self.radius)))
+    }
+}
+
+/// A solid cone with a disk of `radius` at `base`, narrowing to a point at
+/// `apex`. Texture coordinates are laid out as on a `Cylinder`.
+#[derive(Clone, Debug)]
+pub struct Cone<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    frame: Frame,
+    pub height: f64,
+    pub radius: f64,
+    pub material: Mat,
+}
+
+impl<Mat> Cone<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(base: Point, apex: Point, radius: f64, material: Mat) -> Self {
+        Cone { frame: Frame::new(base, apex - base), height: (apex - base).length(), radius, material }
+    }
+}
+
+impl<Mat> Hit<Mat> for Cone<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let o = self.frame.to_local(&ray.origin);
+        let d = self.frame.vector_to_local(&ray.dir);
+        let (h, r) = (self.height, self.radius);
+        let mut closest = None;
+
+        // The side is x^2 + y^2 = (k (h - z))^2, where k is the slope of the
+        // radius with height. Solutions past the apex belong to the mirrored
+        // cone and are thrown out by the height check.
+        let k2 = (r / h) * (r / h);
+        let m = h - o.z;
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y + k2 * m * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * m * m;
+        if let Some((t0, t1)) = solve_quadratic(a, b, c) {
+            for t in [t0, t1] {
+                let z = o.z + t * d.z;
+                if (t_min..=t_max).contains(&t) && (0.0..=h).contains(&z) {
+                    let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+                    // The apex itself has no normal; point it along the axis.
+                    let normal = Vec3::new(x, y, k2 * (h - z));
+                    let normal = if normal.length_squared() > 0.0 { normal.unit_vector() } else { Vec3::Z_HAT };
+                    // Up the side, the point also moves in toward the axis.
+                    let ring = (x * x + y * y).sqrt();
+                    let inward = if ring > 0.0 { Vec3::new(-x, -y, 0.0) * (r / ring) } else { Vec3::default() };
+                    let derivatives = (Vec3::new(-y, x, 0.0) * (2.0 * PI), inward + Vec3::new(0.0, 0.0, h));
+                    keep_closest(&mut closest, (t, normal, (polar_angle(x, y), z / h), derivatives));
+                    break
+                }
+            }
+        }
+        if let Some(hit) = hit_cap(&o, &d, 0.0, -1.0, r, t_min, t_max) {
+            keep_closest(&mut closest, hit);
+        }
+
+        match closest {
+            Some(hit) => { finish_hit(&self.frame, ray, hit, &self.material, hit_record); true }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let apex = self.frame.to_world(&Vec3::new(0.0, 0.0, self.height));
+        let pad = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        Some(disk_box(self.frame.origin, &self.frame.w, self.radius).grow(&(apex + pad)).grow(&(apex - pad)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::MatKind;
+
+    const EPSILON: f64 = 1.0e-9;
+
+    fn closest<'a, H: Hit<MatKind>>(shape: &'a H, ray: &Ray) -> Option<HitRecord<'a, MatKind>> {
+        let mut rec = HitRecord::empty();
+        shape.hit(ray, 0.0, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    fn assert_finite(rec: &HitRecord<MatKind>) {
+        for v in [rec.p, rec.normal, rec.tangent, rec.bitangent] {
+            assert!(v.x.is_finite() && v.y.is_finite() && v.z.is_finite(), "{:?}", rec);
+        }
+        assert!(rec.u.is_finite() && rec.v.is_finite());
+    }
+
+    #[test]
+    fn cylinder_caps_and_side() {
+        let cylinder = Cylinder::new(Point::ORIGIN, Point::new(0.0, 0.0, 2.0), 1.0, MatKind::default());
+        // Down the axis onto the top cap, then out through the base from inside.
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), -Vec3::Z_HAT);
+        let rec = closest(&cylinder, &ray).unwrap();
+        assert!((rec.t - 3.0).abs() < EPSILON && (rec.normal - Vec3::Z_HAT).near_zero());
+        assert_finite(&rec);
+        let ray = Ray::new(Point::new(0.5, 0.0, 1.0), -Vec3::Z_HAT);
+        let rec = closest(&cylinder, &ray).unwrap();
+        assert!((rec.t - 1.0).abs() < EPSILON && rec.front_face == Some(false));
+        assert!((rec.outward_normal() + Vec3::Z_HAT).near_zero());
+        // Across the side.
+        let ray = Ray::new(Point::new(-5.0, 0.0, 1.0), Vec3::X_HAT);
+        let rec = closest(&cylinder, &ray).unwrap();
+        assert!((rec.t - 4.0).abs() < EPSILON && (rec.normal + Vec3::X_HAT).near_zero());
+        assert!((rec.v - 0.5).abs() < EPSILON);
+        // Parallel to the axis outside it, and over the top.
+        assert!(closest(&cylinder, &Ray::new(Point::new(1.5, 0.0, 5.0), -Vec3::Z_HAT)).is_none());
+        assert!(closest(&cylinder, &Ray::new(Point::new(-5.0, 0.0, 2.5), Vec3::X_HAT)).is_none());
+    }
+
+    #[test]
+    fn cone_apex_cap_and_side() {
+        let cone = Cone::new(Point::ORIGIN, Point::new(0.0, 0.0, 1.0), 1.0, MatKind::default());
+        // Straight onto the apex, where the side has no normal of its own.
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), -Vec3::Z_HAT);
+        let rec = closest(&cone, &ray).unwrap();
+        assert!((rec.t - 4.0).abs() < EPSILON, "t = {}", rec.t);
+        assert_finite(&rec);
+        // Up into the base cap, and out through it from inside.
+        let ray = Ray::new(Point::new(0.5, 0.0, -1.0), Vec3::Z_HAT);
+        let rec = closest(&cone, &ray).unwrap();
+        assert!((rec.t - 1.0).abs() < EPSILON && (rec.normal + Vec3::Z_HAT).near_zero());
+        assert!((rec.v - 0.5).abs() < EPSILON);
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.5), -Vec3::Z_HAT);
+        let rec = closest(&cone, &ray).unwrap();
+        assert!((rec.t - 0.5).abs() < EPSILON && rec.front_face == Some(false));
+        // Across the side, halfway up, where the radius is 0.5.
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.5), Vec3::X_HAT);
+        let rec = closest(&cone, &ray).unwrap();
+        assert!((rec.t - 4.5).abs() < EPSILON, "t = {}", rec.t);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 1.0).unit_vector()).near_zero(), "{:?}", rec.normal);
+        // The mirrored cone above the apex isn't part of it.
+        assert!(closest(&cone, &Ray::new(Point::new(-5.0, 0.0, 1.5), Vec3::X_HAT)).is_none());
+    }
+}
@@ -0,0 +1,42 @@
+use crate::render::Point;
+use crate::vec::Vec3;
+
+/// A right-handed orthonormal frame with its z axis along `w`. Shapes with an
+/// axis of symmetry (disks, cylinders, cones, tori) are intersected in their
+/// own frame, where the maths is simplest.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub origin: Point,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Frame {
+    /// A frame at `origin` with z along `axis`; which way x and y point about
+    /// it is arbitrary.
+    pub fn new(origin: Point, axis: Vec3) -> Self {
+        let (w, u, v) = axis.coordinate_system_from();
+        Frame { origin, u, v, w }
+    }
+
+    #[inline]
+    pub fn to_local(&self, p: &Point) -> Vec3 {
+        self.vector_to_local(&(*p - self.origin))
+    }
+
+    #[inline]
+    pub fn vector_to_local(&self, d: &Vec3) -> Vec3 {
+        Vec3::new(d.dot(&self.u), d.dot(&self.v), d.dot(&self.w))
+    }
+
+    #[inline]
+    pub fn to_world(&self, p: &Vec3) -> Point {
+        self.origin + self.vector_to_world(p)
+    }
+
+    #[inline]
+    pub fn vector_to_world(&self, d: &Vec3) -> Vec3 {
+        self.u * d.x + self.v * d.y + self.w * d.z
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::render::Color;
+
+/// A floating point image, stored top row first.
+#[derive(Clone, Debug)]
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+/// Reads a Radiance `.hdr` (RGBE) file, the usual format for environment maps.
+/// Both flat and run-length encoded scanlines are supported, but only the
+/// standard `-Y height +X width` orientation.
+pub fn load_hdr(path: &Path) -> Result<HdrImage> {
+    let bytes = fs::read(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+
+    // The header is a series of text lines ended by an empty one, followed by
+    // the resolution line.
+    let mut pos = 0;
+    let next_line = |pos: &mut usize| -> Option<String> {
+        let end = bytes[*pos..].iter().position(|&b| b == b'\n')? + *pos;
+        let line = String::from_utf8_lossy(&bytes[*pos..end]).into_owned();
+        *pos = end + 1;
+        Some(line)
+    };
+
+    let magic = next_line(&mut pos).ok_or_else(|| invalid("empty file"))?;
+    if !magic.starts_with("#?") { return Err(invalid("not a Radiance HDR file")) }
+    loop {
+        let line = next_line(&mut pos).ok_or_else(|| invalid("unterminated header"))?;
+        if line.is_empty() { break }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" { return Err(invalid(&format!("unsupported format {}", format))) }
+        }
+    }
+
+    let resolution = next_line(&mut pos).ok_or_else(|| invalid("missing resolution"))?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match fields.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>().map_err(|_| invalid("bad height"))?,
+            w.parse::<usize>().map_err(|_| invalid("bad width"))?,
+        ),
+        _ => return Err(invalid(&format!("unsupported orientation `{}`", resolution))),
+    };
+    if width == 0 || height == 0 { return Err(invalid("image is empty")) }
+
+    let data = &bytes[pos..];
+    let mut pos = 0;
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        pos = read_scanline(data, pos, &mut scanline).ok_or_else(|| invalid("truncated pixel data"))?;
+        pixels.extend(scanline.iter().map(rgbe_to_color));
+    }
+
+    Ok(HdrImage { width, height, pixels })
+}
+
+/// Decodes one scanline starting at `pos`, returning the position just past it.
+fn read_scanline(data: &[u8], mut pos: usize, scanline: &mut [[u8; 4]]) -> Option<usize> {
+    let width = scanline.len();
+    let header = data.get(pos..pos + 4)?;
+    let is_rle = (8..0x8000).contains(&width)
+        && header[0] == 2
+        && header[1] == 2
+        && ((header[2] as usize) << 8 | header[3] as usize) == width;
+
+    if !is_rle {
+        for pixel in scanline.iter_mut() {
+            pixel.copy_from_slice(data.get(pos..pos + 4)?);
+            pos += 4;
+        }
+        return Some(pos)
+    }
+
+    // New style RLE: each of the four channels is encoded separately as a
+    // sequence of runs (count > 128) and literal dumps.
+    pos += 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(pos)? as usize;
+            pos += 1;
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(pos)?;
+                pos += 1;
+                if count == 0 || x + count > width { return None }
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width { return None }
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(data.get(pos..pos + count)?) {
+                    pixel[channel] = value;
+                }
+                pos += count;
+                x += count;
+            }
+        }
+    }
+    Some(pos)
+}
+
+#[inline]
+fn rgbe_to_color(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 { return Color::default() }
+    let scale = 2f64.powi(rgbe[3] as i32 - 136);
+    Color::new(rgbe[0] as f64 * scale, rgbe[1] as f64 * scale, rgbe[2] as f64 * scale)
+}
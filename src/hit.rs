@@ -1,31 +1,92 @@
+use crate::aabb::Aabb;
+use crate::csg::Csg;
+use crate::cylinder::{Cone, Cylinder};
 use crate::material::Material;
+use crate::medium::ConstantMedium;
+use crate::mesh::Mesh;
+use crate::moving_sphere::MovingSphere;
+use crate::plane::{Disk, Plane};
+use crate::quad::{BoxShape, Quad};
 use crate::ray::Ray;
+use crate::rect::{XyRect, XzRect, YzRect};
 use crate::render::Point;
+use crate::sdf::Sdf;
 use crate::sphere::Sphere;
+use crate::torus::Torus;
+use crate::transform::Transform;
+use crate::triangle::Triangle;
 use crate::vec::Vec3;
 
-#[derive(Clone, Copy, Debug)]
-pub struct HitRecord<Mat> 
+/// What a ray hit and where. The material is borrowed from the object that
+/// was hit, so finding a closer hit during traversal never copies one.
+#[derive(Debug)]
+pub struct HitRecord<'a, Mat>
 where
-    Mat: Material + Copy + Default,
+    Mat: Material + Clone + Default,
 {
     pub p: Point,
     pub normal: Vec3,
     pub t: f64,
-    pub material: Mat,
+    /// Surface coordinates of the hit, for looking up textures.
+    pub u: f64,
+    pub v: f64,
+    /// `None` only in an empty record that hasn't been hit yet.
+    pub material: Option<&'a Mat>,
     pub front_face: Option<bool>,
+    /// Unit vectors across the surface, perpendicular to each other and to the
+    /// normal, for materials that perturb the normal in tangent space. The
+    /// tangent points the way `u` increases and the bitangent the way `v`
+    /// does, on the outside of the surface whichever side was hit; shapes that
+    /// don't give one get zero; see `tangent_frame`.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    /// How far `p` moves as `u` and `v` increase, so that slopes read off a
+    /// texture can be turned into slopes across the surface. Zero where the
+    /// shape doesn't say.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
 }
 
-impl<Mat> HitRecord<Mat>
+// Written out rather than derived, which would want `Mat: Copy` as well.
+impl<Mat> Clone for HitRecord<'_, Mat>
 where
-    Mat: Material + Copy + Default,
+    Mat: Material + Clone + Default,
 {
-    pub fn new(p: Point, normal: Vec3, t: f64, material: Mat, front_face: Option<bool>) -> Self {
-        HitRecord { p, normal, t, material, front_face }
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Mat> Copy for HitRecord<'_, Mat> where Mat: Material + Clone + Default {}
+
+impl<'a, Mat> HitRecord<'a, Mat>
+where
+    Mat: Material + Clone + Default,
+{
+    pub fn new(p: Point, normal: Vec3, t: f64, material: &'a Mat, front_face: Option<bool>) -> Self {
+        let zero = Vec3::default();
+        HitRecord {
+            p, normal, t, u: 0.0, v: 0.0, material: Some(material), front_face,
+            tangent: zero, bitangent: zero, dpdu: zero, dpdv: zero,
+        }
     }
 
     pub fn empty() -> Self {
-        Self::new(Point::ORIGIN, Vec3::X_HAT, 0.0, Mat::default(), None)
+        let zero = Vec3::default();
+        HitRecord {
+            p: Point::ORIGIN, normal: Vec3::X_HAT, t: 0.0, u: 0.0, v: 0.0, material: None, front_face: None,
+            tangent: zero, bitangent: zero, dpdu: zero, dpdv: zero,
+        }
+    }
+
+    /// The material of whatever was hit.
+    ///
+    /// # Panics
+    ///
+    /// If nothing has been hit yet.
+    #[inline]
+    pub fn material(&self) -> &'a Mat {
+        self.material.expect("hit record has no material before a hit")
     }
 
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: &Vec3) {
@@ -36,44 +97,184 @@ where
             None => panic!("Impossible to reach error")
         }
     }
+
+    /// The normal on the outside of the surface, whichever side was hit.
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face == Some(false) { -self.normal } else { self.normal }
+    }
+
+    /// Records how `p` changes with `u` and `v` and lines the tangent frame up
+    /// with them. Neither needs to lie exactly in the surface; `dpdv` only
+    /// decides which way the bitangent points, so mirrored texture coordinates
+    /// give a left-handed frame. Call it after the normal is set,
+    /// for every hit: a record handed from one candidate hit to the next keeps
+    /// whatever frame the last one left otherwise.
+    pub fn set_tangents(&mut self, dpdu: &Vec3, dpdv: &Vec3) {
+        (self.dpdu, self.dpdv) = (*dpdu, *dpdv);
+        let n = self.outward_normal();
+        let tangent = *dpdu - n * n.dot(dpdu);
+        if tangent.near_zero() {
+            (self.tangent, self.bitangent) = (Vec3::default(), Vec3::default());
+            return
+        }
+        self.tangent = tangent.unit_vector();
+        self.bitangent = n.cross(&self.tangent);
+        if self.bitangent.dot(dpdv) < 0.0 { self.bitangent = -self.bitangent; }
+    }
+
+    /// The tangent and bitangent, or an arbitrary pair across the surface where
+    /// the shape has none to give. Only normal maps need a frame, so the
+    /// fallback is left until one asks for it.
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        if !self.tangent.near_zero() { return (self.tangent, self.bitangent) }
+        let (_, tangent, bitangent) = self.outward_normal().coordinate_system_from();
+        (tangent, bitangent)
+    }
 }
 
 pub trait Hit<Mat>
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<Mat>) -> bool;
+    /// Finds the closest hit with `t` in `[t_min, t_max]`, filling in `rec` and
+    /// returning true. `rec` is left alone on a miss, so aggregates can pass the
+    /// same record to every child and shrink `t_max` as they go.
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a, Mat>) -> bool;
+
+    /// Every hit with `t` in `[t_min, t_max]`, nearest first. By default it
+    /// steps along the ray from one `hit` to the next; see `hits_by_stepping`.
+    fn hits<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'a, Mat>> {
+        hits_by_stepping(self, ray, t_min, t_max)
+    }
+
+    /// Box enclosing everything this object can be hit at, or `None` if it is
+    /// unbounded. Objects without a box are never placed inside a BVH node.
+    fn bounding_box(&self) -> Option<Aabb>;
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Most hits a single `hits_by_stepping` query returns, in case something keeps
+/// reporting hits just past the last one.
+const MAX_HITS: usize = 64;
+
+/// Collects hits along `ray` by asking `object` for the closest one, then the
+/// closest just past that, and so on.
+pub fn hits_by_stepping<'a, Mat, H>(object: &'a H, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'a, Mat>>
+where
+    Mat: Material + Clone + Default,
+    H: Hit<Mat> + ?Sized,
+{
+    let mut hits = Vec::new();
+    let mut rec = HitRecord::empty();
+    let mut t = t_min;
+    while hits.len() < MAX_HITS && object.hit(ray, t, t_max, &mut rec) {
+        t = step_past(rec.t);
+        hits.push(rec);
+    }
+    hits
+}
+
+/// Where to look for the next hit after one at `t`: far enough past it not to
+/// find the same one again.
+#[inline]
+pub(crate) fn step_past(t: f64) -> f64 {
+    t + 1.0e-7 * t.abs().max(1.0)
+}
+
+#[derive(Clone, Debug)]
 pub enum Hittable<Mat> 
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
     Sphere(Sphere<Mat>),
+    MovingSphere(MovingSphere<Mat>),
+    Triangle(Triangle<Mat>),
+    Mesh(Mesh<Mat>),
+    XyRect(XyRect<Mat>),
+    XzRect(XzRect<Mat>),
+    YzRect(YzRect<Mat>),
+    Quad(Quad<Mat>),
+    BoxShape(Box<BoxShape<Mat>>),
+    Plane(Plane<Mat>),
+    Disk(Disk<Mat>),
+    Cylinder(Cylinder<Mat>),
+    Cone(Cone<Mat>),
+    Torus(Torus<Mat>),
+    ConstantMedium(ConstantMedium<Mat>),
+    Transform(Box<Transform<Mat>>),
+    Csg(Csg<Mat>),
+    Sdf(Sdf<Mat>),
 }
 
 impl<Mat> Hit<Mat> for Hittable<Mat> 
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<Mat>) -> bool {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a, Mat>) -> bool {
+        match self {
+            Self::Sphere(s) => s.hit(ray, t_min, t_max, rec),
+            Self::MovingSphere(s) => s.hit(ray, t_min, t_max, rec),
+            Self::Triangle(t) => t.hit(ray, t_min, t_max, rec),
+            Self::Mesh(m) => m.hit(ray, t_min, t_max, rec),
+            Self::XyRect(r) => r.hit(ray, t_min, t_max, rec),
+            Self::XzRect(r) => r.hit(ray, t_min, t_max, rec),
+            Self::YzRect(r) => r.hit(ray, t_min, t_max, rec),
+            Self::Quad(q) => q.hit(ray, t_min, t_max, rec),
+            Self::BoxShape(b) => b.hit(ray, t_min, t_max, rec),
+            Self::Plane(p) => p.hit(ray, t_min, t_max, rec),
+            Self::Disk(d) => d.hit(ray, t_min, t_max, rec),
+            Self::Cylinder(c) => c.hit(ray, t_min, t_max, rec),
+            Self::Cone(c) => c.hit(ray, t_min, t_max, rec),
+            Self::Torus(t) => t.hit(ray, t_min, t_max, rec),
+            Self::ConstantMedium(c) => c.hit(ray, t_min, t_max, rec),
+            Self::Transform(t) => t.hit(ray, t_min, t_max, rec),
+            Self::Csg(c) => c.hit(ray, t_min, t_max, rec),
+            Self::Sdf(s) => s.hit(ray, t_min, t_max, rec),
+        }
+    }
+
+    fn hits<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'a, Mat>> {
+        match self {
+            Self::Csg(c) => c.hits(ray, t_min, t_max),
+            Self::Transform(t) => t.hits(ray, t_min, t_max),
+            _ => hits_by_stepping(self, ray, t_min, t_max),
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
         match self {
-            Self::Sphere(s) => return s.hit(ray, t_min, t_max, rec)
+            Self::Sphere(s) => s.bounding_box(),
+            Self::MovingSphere(s) => s.bounding_box(),
+            Self::Triangle(t) => t.bounding_box(),
+            Self::Mesh(m) => m.bounding_box(),
+            Self::XyRect(r) => r.bounding_box(),
+            Self::XzRect(r) => r.bounding_box(),
+            Self::YzRect(r) => r.bounding_box(),
+            Self::Quad(q) => q.bounding_box(),
+            Self::BoxShape(b) => b.bounding_box(),
+            Self::Plane(p) => p.bounding_box(),
+            Self::Disk(d) => d.bounding_box(),
+            Self::Cylinder(c) => c.bounding_box(),
+            Self::Cone(c) => c.bounding_box(),
+            Self::Torus(t) => t.bounding_box(),
+            Self::ConstantMedium(c) => c.bounding_box(),
+            Self::Transform(t) => t.bounding_box(),
+            Self::Csg(c) => c.bounding_box(),
+            Self::Sdf(s) => s.bounding_box(),
         }
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct HitList<Mat>
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
     inner: Vec<Hittable<Mat>>,
 }
 
 impl<Mat> HitList<Mat>
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
     pub fn new() -> Self {
         HitList { inner: Vec::new() }
@@ -82,30 +283,126 @@ where
     pub fn push(&mut self, hittable: Hittable<Mat>) {
         self.inner.push(hittable)
     }
+
+    #[allow(unused)]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[allow(unused)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Hittable<Mat>> {
+        self.inner.iter()
+    }
+
+    pub fn into_inner(self) -> Vec<Hittable<Mat>> {
+        self.inner
+    }
+}
+
+impl<Mat> Default for HitList<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<Mat> Hit<Mat> for HitList<Mat> 
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<Mat>) -> bool {
-        let mut temp_rec: HitRecord<Mat> = HitRecord::empty();
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a, Mat>) -> bool {
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
 
         for hit in self.inner.iter() {
-            if hit.hit(ray, t_min, closest_so_far, &mut temp_rec) {
+            if hit.hit(ray, t_min, closest_so_far, rec) {
                 hit_anything = true;
-                closest_so_far = temp_rec.t;
-                rec.p = temp_rec.p;
-                rec.normal = temp_rec.normal;
-                rec.t = temp_rec.t;
-                rec.material = temp_rec.material;
-                rec.front_face = temp_rec.front_face;
-                //*rec = temp_rec;
+                closest_so_far = rec.t;
             }
         }
 
         hit_anything
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut iter = self.inner.iter();
+        let first = iter.next()?.bounding_box()?;
+        iter.try_fold(first, |acc, hit| Some(acc.surrounding(&hit.bounding_box()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::MatKind;
+    use crate::matrix::Matrix4;
+
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn derivatives_follow_the_texture_coordinates() {
+        // For each shape, a ray at a target on its surface and more at targets
+        // a little off to the side. Between the hits `p` should move as far
+        // as `dpdu` and `dpdv` say the change in (u, v) takes it.
+        let m = MatKind::default;
+        let triangle = Triangle::new(
+            [Point::ORIGIN, Point::new(2.0, 0.0, 0.0), Point::new(0.0, 3.0, 1.0)],
+            None,
+            Some([(0.1, 0.2), (0.9, 0.3), (0.4, 0.8)]),
+            m(),
+        );
+        let squash = Matrix4::rotation(Vec3::new(1.0, 2.0, 3.0), 30.0) * Matrix4::scaling(Vec3::new(2.0, 1.0, 0.5));
+        let ellipsoid = Transform::new(Arc::new(Hittable::Sphere(Sphere::new(Point::ORIGIN, 1.0, m()))), squash);
+        let (base, top) = (Point::ORIGIN, Point::new(0.0, 0.0, 3.0));
+        let cylinder = Hittable::Cylinder(Cylinder::new(base, top, 1.0, m()));
+        let cone = Hittable::Cone(Cone::new(base, top, 1.0, m()));
+        let quad = Quad::new(Point::new(-1.0, -1.0, 0.0), Vec3::new(3.0, 0.0, 0.0), Vec3::new(0.5, 2.0, 0.0), m());
+        let sphere = Sphere::new(Point::ORIGIN, 2.0, m());
+        let plane = Plane::new(Point::ORIGIN, Vec3::new(0.0, 1.0, 1.0), m());
+        let disk = Disk::new(Point::ORIGIN, Vec3::new(1.0, 1.0, 0.0), 2.0, m());
+        let torus = Torus::new(Point::ORIGIN, Vec3::Z_HAT, 2.0, 0.5, m());
+        let above = |x: f64, y: f64, z: f64| (Point::new(x, y, z), Point::new(x, y, 0.0));
+        let cases = [
+            (Hittable::Sphere(sphere), (Point::new(3.0, 4.0, 5.0), Point::new(0.3, 0.5, 0.0))),
+            (Hittable::Quad(quad), above(0.2, 0.3, 5.0)),
+            (Hittable::XyRect(XyRect::new(-2.0, 2.0, -1.0, 3.0, 0.0, m())), above(0.5, 0.5, 4.0)),
+            (Hittable::Plane(plane), (Point::new(1.0, 2.0, 3.0), Point::new(0.3, 0.4, -0.4))),
+            (Hittable::Disk(disk), (Point::new(3.0, 3.0, 1.0), Point::new(0.5, -0.5, 0.7))),
+            (cylinder.clone(), (Point::new(5.0, 1.0, 1.5), Point::new(0.0, 0.0, 1.5))),
+            (cylinder, above(0.3, 0.2, 6.0)),
+            (cone.clone(), (Point::new(5.0, 0.5, 0.5), Point::new(0.0, 0.0, 0.5))),
+            (cone, above(0.3, 0.2, -5.0)),
+            (Hittable::Torus(torus), above(2.0, 0.3, 5.0)),
+            (Hittable::Triangle(triangle), above(0.5, 0.5, 5.0)),
+            (Hittable::Transform(Box::new(ellipsoid.unwrap())), (Point::new(3.0, 2.0, 4.0), Point::ORIGIN)),
+        ];
+
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for (shape, (origin, target)) in cases {
+            let hit_toward = |target: Point| {
+                let mut rec = HitRecord::empty();
+                assert!(shape.hit(&Ray::new(origin, target - origin), 0.001, f64::INFINITY, &mut rec), "{:?}", shape);
+                rec
+            };
+            let rec = hit_toward(target);
+            for _ in 0..20 {
+                let near = hit_toward(target + Vec3::random(-1.0e-4, 1.0e-4, &mut rng));
+                let moved = near.p - rec.p;
+                let predicted = rec.dpdu * (near.u - rec.u) + rec.dpdv * (near.v - rec.v);
+                assert!(
+                    (moved - predicted).length() < 1.0e-3 * moved.length() + 1.0e-12,
+                    "{:?}: moved {:?}, predicted {:?}", shape, moved, predicted,
+                );
+            }
+        }
+    }
 }
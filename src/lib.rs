@@ -0,0 +1,37 @@
+//! A parallel Monte Carlo path tracer, following Peter Shirley's Ray Tracing in
+//! One Weekend series. Load or build a `scene::Scene`, hand it to a
+//! `renderer::Renderer` and get back an `renderer::Image` of linear radiance.
+
+pub mod aabb;
+pub mod accum;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod csg;
+pub mod cylinder;
+pub mod frame;
+pub mod hdr;
+pub mod hit;
+pub mod light;
+pub mod material;
+pub mod matrix;
+pub mod medium;
+pub mod mesh;
+pub mod microfacet;
+pub mod moving_sphere;
+pub mod obj;
+pub mod output;
+pub mod plane;
+pub mod quad;
+pub mod ray;
+pub mod rect;
+pub mod render;
+pub mod renderer;
+pub mod scene;
+pub mod sdf;
+pub mod sphere;
+pub mod texture;
+pub mod torus;
+pub mod transform;
+pub mod triangle;
+pub mod vec;
@@ -0,0 +1,295 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hit::{Hit, HitList, HitRecord, Hittable};
+use crate::material::MatKind;
+use crate::matrix::Matrix4;
+use crate::quad::Quad;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::sphere::Sphere;
+use crate::triangle::Triangle;
+use crate::vec::Vec3;
+
+use rand::Rng;
+
+/// One emitter that can be sampled directly. Rectangles and the sides of
+/// boxes are kept as the equivalent quads, and the faces of meshes as lone
+/// triangles.
+#[derive(Clone, Debug)]
+enum Light {
+    Sphere(Sphere<MatKind>),
+    Quad(Quad<MatKind>),
+    Triangle(Triangle<MatKind>),
+}
+
+impl Light {
+    fn sample_direction<R: Rng + ?Sized>(&self, origin: Point, rng: &mut R) -> Option<Vec3> {
+        match self {
+            Self::Sphere(sphere) => sphere.sample_direction(origin, rng),
+            Self::Quad(quad) => Some(quad.sample_direction(origin, rng)),
+            Self::Triangle(triangle) => Some(triangle.sample_direction(origin, rng)),
+        }
+    }
+
+    fn pdf_direction(&self, origin: Point, dir: &Vec3) -> f64 {
+        match self {
+            Self::Sphere(sphere) => sphere.pdf_direction(origin, dir),
+            Self::Quad(quad) => quad.pdf_direction(origin, dir),
+            Self::Triangle(triangle) => triangle.pdf_direction(origin, dir),
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self {
+            Self::Sphere(sphere) => sphere.bounding_box(),
+            Self::Quad(quad) => quad.bounding_box(),
+            Self::Triangle(triangle) => triangle.bounding_box(),
+        }
+    }
+}
+
+/// The emitters the MIS integrator samples directly: spheres, quads, boxes,
+/// rectangles, triangles and meshes with an emissive material, including ones
+/// placed by transforms. Other emissive shapes are still found by BSDF
+/// sampling alone, which is unbiased, just noisier; `unsampled` counts them.
+#[derive(Clone, Debug, Default)]
+pub struct Lights {
+    lights: Vec<Light>,
+    /// Indices into `lights` by where they are, so working out the density of
+    /// a direction only looks at the lights it could reach.
+    bvh: Bvh<MatKind, usize>,
+    unsampled: usize,
+}
+
+impl Lights {
+    /// Collects every emitter in `world` that can be sampled, in world space.
+    pub fn new(world: &HitList<MatKind>) -> Self {
+        let mut lights = Lights::default();
+        for hittable in world.iter() {
+            lights.collect(hittable, &Matrix4::IDENTITY);
+        }
+        let bvh = Bvh::with_boxes((0..lights.len()).collect(), |&i| lights.lights[i].bounding_box());
+        lights.bvh = bvh;
+        lights
+    }
+
+    /// Adds the emitters in `hittable`, which `matrix` places in the world.
+    fn collect(&mut self, hittable: &Hittable<MatKind>, matrix: &Matrix4) {
+        let quad = |quad: &Quad<MatKind>| {
+            let q = matrix.transform_point(&quad.q);
+            let u = matrix.transform_vector(&quad.u);
+            Light::Quad(Quad::new(q, u, matrix.transform_vector(&quad.v), quad.material.clone()))
+        };
+        let triangle = |vertices: [Point; 3], material: &MatKind| {
+            Light::Triangle(Triangle::new(vertices.map(|p| matrix.transform_point(&p)), None, None, material.clone()))
+        };
+        match hittable {
+            Hittable::Sphere(sphere) if sphere.material.is_emissive() => match uniform_scale(matrix) {
+                Some(scale) => {
+                    let center = matrix.transform_point(&sphere.center);
+                    self.lights.push(Light::Sphere(Sphere::new(center, sphere.r * scale, sphere.material.clone())));
+                }
+                // Squashed into an ellipsoid, which sphere sampling doesn't cover.
+                None => self.unsampled += 1,
+            },
+            Hittable::Quad(q) if q.material.is_emissive() => self.lights.push(quad(q)),
+            Hittable::XyRect(rect) if rect.material.is_emissive() => self.lights.push(quad(&rect.to_quad())),
+            Hittable::XzRect(rect) if rect.material.is_emissive() => self.lights.push(quad(&rect.to_quad())),
+            Hittable::YzRect(rect) if rect.material.is_emissive() => self.lights.push(quad(&rect.to_quad())),
+            Hittable::BoxShape(shape) if shape.sides()[0].material.is_emissive() => {
+                self.lights.extend(shape.sides().iter().map(quad));
+            }
+            Hittable::Triangle(tri) if tri.material.is_emissive() => {
+                self.lights.push(triangle(tri.vertices, &tri.material));
+            }
+            Hittable::Mesh(mesh) if mesh.material.is_emissive() => {
+                self.lights.extend(mesh.triangles().map(|vertices| triangle(vertices, &mesh.material)));
+            }
+            Hittable::Transform(transform) => self.collect(&transform.object, &(*matrix * *transform.matrix())),
+            other if contains_emitter(other) => self.unsampled += 1,
+            _ => {}
+        }
+    }
+
+    /// How many emissive objects weren't collected because they can't be
+    /// sampled directly (emissive planes, cylinders, CSG and so on).
+    pub fn unsampled(&self) -> usize {
+        self.unsampled
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Picks a light uniformly and a direction toward it from `origin`.
+    pub fn sample<R: Rng + ?Sized>(&self, origin: Point, rng: &mut R) -> Option<Vec3> {
+        if self.is_empty() { return None }
+        self.lights[rng.gen_range(0..self.len())].sample_direction(origin, rng)
+    }
+
+    /// Solid angle density of `sample` for `dir`. Where several lights cover
+    /// the same direction, any of them could have produced it, so their
+    /// densities add up. Only lights whose boxes the ray passes through can
+    /// cover it, which the BVH finds without looking at the rest.
+    pub fn pdf(&self, origin: Point, dir: &Vec3) -> f64 {
+        if self.is_empty() { return 0.0 }
+        let ray = Ray::new(origin, *dir);
+        let mut total = 0.0;
+        // Never reporting a hit keeps the traversal from narrowing down to the
+        // nearest light, so it visits every one along the ray.
+        self.bvh.traverse(&ray, 0.0, f64::INFINITY, &mut HitRecord::empty(), |&i, _, _| {
+            total += self.lights[i].pdf_direction(origin, dir);
+            false
+        });
+        total / self.len() as f64
+    }
+}
+
+/// The scale factor of `matrix` if it scales equally in every direction, so
+/// spheres stay spheres.
+fn uniform_scale(matrix: &Matrix4) -> Option<f64> {
+    let axes = [Vec3::X_HAT, Vec3::Y_HAT, Vec3::Z_HAT].map(|axis| matrix.transform_vector(&axis));
+    let scale = axes[0].length();
+    let tolerance = 1.0e-9 * scale;
+    let equal_lengths = axes.iter().all(|axis| (axis.length() - scale).abs() <= tolerance);
+    let orthogonal = axes[0].dot(&axes[1]).abs() <= tolerance * scale
+        && axes[1].dot(&axes[2]).abs() <= tolerance * scale
+        && axes[2].dot(&axes[0]).abs() <= tolerance * scale;
+    if equal_lengths && orthogonal { Some(scale) } else { None }
+}
+
+/// Whether anything in `hittable` gives off light.
+fn contains_emitter(hittable: &Hittable<MatKind>) -> bool {
+    match hittable {
+        Hittable::Sphere(o) => o.material.is_emissive(),
+        Hittable::MovingSphere(o) => o.material.is_emissive(),
+        Hittable::Triangle(o) => o.material.is_emissive(),
+        Hittable::Mesh(o) => o.material.is_emissive(),
+        Hittable::XyRect(o) => o.material.is_emissive(),
+        Hittable::XzRect(o) => o.material.is_emissive(),
+        Hittable::YzRect(o) => o.material.is_emissive(),
+        Hittable::Quad(o) => o.material.is_emissive(),
+        Hittable::BoxShape(o) => o.sides()[0].material.is_emissive(),
+        Hittable::Plane(o) => o.material.is_emissive(),
+        Hittable::Disk(o) => o.material.is_emissive(),
+        Hittable::Cylinder(o) => o.material.is_emissive(),
+        Hittable::Cone(o) => o.material.is_emissive(),
+        Hittable::Torus(o) => o.material.is_emissive(),
+        Hittable::Sdf(o) => o.material.is_emissive(),
+        Hittable::ConstantMedium(_) => false,
+        Hittable::Transform(o) => contains_emitter(&o.object),
+        Hittable::Csg(o) => contains_emitter(&o.left) || contains_emitter(&o.right),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::material::{DiffuseLight, Lambertian, Principled};
+    use crate::mesh::{Mesh, MeshData, MeshFace};
+    use crate::plane::Disk;
+    use crate::quad::BoxShape;
+    use crate::render::Color;
+    use crate::transform::Transform;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn lamp() -> MatKind {
+        MatKind::DiffuseLight(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)))
+    }
+
+    fn transformed(object: Hittable<MatKind>, matrix: Matrix4) -> Hittable<MatKind> {
+        Hittable::Transform(Box::new(Transform::new(Arc::new(object), matrix).unwrap()))
+    }
+
+    #[test]
+    fn collects_emitters_wherever_they_are() {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let data = MeshData { positions: vec![origin, Vec3::X_HAT, Vec3::new(1.0, 1.0, 0.0), Vec3::Y_HAT], ..MeshData::default() };
+        let faces = vec![MeshFace::new([0, 1, 2], None, None), MeshFace::new([0, 2, 3], None, None)];
+        let glowing = MatKind::Principled(Principled { emission: Color::new(1.0, 1.0, 1.0), ..Principled::default() });
+        let grey = MatKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+
+        let mut world = HitList::new();
+        world.push(Hittable::Sphere(Sphere::new(Point::new(0.0, 5.0, 0.0), 1.0, glowing)));
+        world.push(Hittable::Sphere(Sphere::new(Point::new(0.0, -5.0, 0.0), 1.0, grey)));
+        world.push(Hittable::Triangle(Triangle::new([origin, Vec3::X_HAT, Vec3::Y_HAT], None, None, lamp())));
+        world.push(Hittable::Mesh(Mesh::new(Arc::new(data), faces, lamp())));
+        let quad = Hittable::Quad(Quad::new(origin, Vec3::X_HAT, Vec3::Y_HAT, lamp()));
+        let matrix = Matrix4::translation(Vec3::new(0.0, 0.0, -3.0)) * Matrix4::scaling(Vec3::new(2.0, 3.0, 1.0));
+        world.push(transformed(quad, matrix));
+        let sphere = Hittable::Sphere(Sphere::new(origin, 1.0, lamp()));
+        let matrix = Matrix4::rotation(Vec3::Y_HAT, 30.0) * Matrix4::scaling(Vec3::new(2.0, 2.0, 2.0));
+        world.push(transformed(sphere.clone(), matrix));
+        // Neither an ellipsoid nor a disk can be sampled.
+        world.push(transformed(sphere, Matrix4::scaling(Vec3::new(1.0, 2.0, 1.0))));
+        world.push(Hittable::Disk(Disk::new(origin, Vec3::Y_HAT, 1.0, lamp())));
+
+        let lights = Lights::new(&world);
+        let spheres: Vec<&Sphere<MatKind>> =
+            lights.lights.iter().filter_map(|light| match light { Light::Sphere(s) => Some(s), _ => None }).collect();
+        let quads: Vec<&Quad<MatKind>> =
+            lights.lights.iter().filter_map(|light| match light { Light::Quad(q) => Some(q), _ => None }).collect();
+        assert_eq!((spheres.len(), quads.len(), lights.len()), (2, 1, 6));
+        assert_eq!(lights.unsampled(), 2);
+        assert!((spheres[1].r - 2.0).abs() < 1.0e-9);
+        assert!((quads[0].q - Point::new(0.0, 0.0, -3.0)).near_zero() && (quads[0].area() - 6.0).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn pdf_adds_up_every_light_along_the_direction() {
+        // A row of lamps one behind the other down -z, plus a sphere and a
+        // box off to the side, and directions scattered all over them.
+        let mut world = HitList::new();
+        for i in 0..20 {
+            let q = Point::new(-0.5 + 0.05 * i as f64, -0.5, -2.0 - i as f64);
+            world.push(Hittable::Quad(Quad::new(q, Vec3::X_HAT, Vec3::Y_HAT, lamp())));
+        }
+        world.push(Hittable::Sphere(Sphere::new(Point::new(2.0, 0.0, -5.0), 1.0, lamp())));
+        let shape = BoxShape::new(Point::new(-3.0, -1.0, -6.0), Point::new(-2.0, 1.0, -4.0), lamp());
+        world.push(Hittable::BoxShape(Box::new(shape)));
+        let lights = Lights::new(&world);
+
+        let origin = Point::new(0.1, 0.2, 0.0);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mut covered = 0;
+        for _ in 0..2000 {
+            let dir = lights.sample(origin, &mut rng).unwrap();
+            let every: f64 = lights.lights.iter().map(|light| light.pdf_direction(origin, &dir)).sum();
+            let expected = every / lights.len() as f64;
+            let pdf = lights.pdf(origin, &dir);
+            assert!((pdf - expected).abs() <= 1.0e-12 * expected, "{} vs {}", pdf, expected);
+            if lights.lights.iter().filter(|light| light.pdf_direction(origin, &dir) > 0.0).count() > 1 {
+                covered += 1;
+            }
+        }
+        // Plenty of the directions pass through several lights at once.
+        assert!(covered > 500);
+        // And the ones that miss every light have no density.
+        assert_eq!(lights.pdf(origin, &Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn triangle_pdf_matches_its_samples() {
+        // Averaging 1 / pdf over the samples estimates the solid angle the
+        // triangle fills, which for a small, distant one facing the origin is
+        // close to area / distance^2.
+        let vertices = [Point::new(-0.1, -0.1, -10.0), Point::new(0.1, -0.1, -10.0), Point::new(0.0, 0.1, -10.0)];
+        let triangle = Triangle::new(vertices, None, None, lamp());
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let n = 10_000;
+        let solid_angle: f64 = (0..n)
+            .map(|_| 1.0 / triangle.pdf_direction(origin, &triangle.sample_direction(origin, &mut rng)))
+            .sum::<f64>() / n as f64;
+        let expected = triangle.area() / 100.0;
+        assert!((solid_angle - expected).abs() < 1.0e-3 * expected, "{} vs {}", solid_angle, expected);
+    }
+}
@@ -1,33 +1,29 @@
-use std::fs::File;
-use std::io::{Result, Write};
-
-mod camera;
-mod hit;
-mod material;
-mod ray;
-mod render;
-mod sphere;
-mod vec;
-
-use crate::camera::Camera;
-use crate::hit::{HitList, Hittable};
-use crate::material::{Dielectric, Lambertian, Metal, MatKind};
-use crate::render::{Color, Point, ray_color};
-use crate::sphere::Sphere;
-use crate::vec::Vec3;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ray_trace::accum::Accumulation;
+use ray_trace::background::Background;
+use ray_trace::output::ImageFormat;
+use ray_trace::render::{Integrator, RenderRng};
+use ray_trace::renderer::{RenderSettings, Renderer};
+use ray_trace::scene::{random_scene, CameraDesc, Scene};
 
 use clap::Parser;
-use indicatif::ParallelProgressIterator;
+use indicatif::ProgressBar;
 use rand::prelude::*;
-use rayon::prelude::*;
 
 #[derive(Parser)]
 #[clap(name = "ray-trace",
        author = "Brent Mode <bmode@wisc.edu>",
        version,
        about = "Parallelized MC ray tracing renderer written in Rust",
-       long_about = "For now, this is a demo that only creates one semi-random scene")]
+       long_about = "Renders a scene described in a TOML or JSON file, or the book's cover scene if none is given")]
 struct Cli {
+    /// Scene description (.toml or .json). Image settings given on the command line override the file.
+    #[clap(long)]
+    scene: Option<PathBuf>,
+    /// Output image; the format follows the extension: .png, .ppm (binary) or .pfm (linear float).
     #[clap(short, long)]
     out: Option<String>,
     #[clap(short, long)]
@@ -38,123 +34,149 @@ struct Cli {
     samples: Option<usize>,
     #[clap(short, long)]
     depth: Option<isize>,
+    /// What rays escaping the scene see: sky, black, white, r,g,b, gradient:r,g,b:r,g,b[:x,y,z] (bottom
+    /// and top colors, and the up axis) or an equirectangular .hdr map.
+    #[clap(short, long)]
+    background: Option<String>,
+    /// How light is gathered: path is the book's plain random walk; mis samples lights
+    /// directly as well as by bouncing, which is much less noisy with small lights.
+    #[clap(long, default_value = "path")]
+    integrator: Integrator,
+    /// Seed for every random choice, from the cover scene to the samples. The same seed
+    /// and settings always give the same image. Picked at random if not given.
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Render progressively in passes of this many samples per pixel, updating the output
+    /// image after every pass (or every --checkpoint-secs) until --samples is reached.
+    #[clap(long)]
+    pass_samples: Option<usize>,
+    /// Only update the output image and accumulation file if this many seconds have passed
+    /// since the last update.
+    #[clap(long)]
+    checkpoint_secs: Option<f64>,
+    /// Save the raw accumulation buffer here alongside every image update, so the render can
+    /// be continued later with --resume.
+    #[clap(long)]
+    accum: Option<PathBuf>,
+    /// Continue a render from a saved accumulation file, adding samples until --samples is
+    /// reached. The seed stored in the file is used unless --seed is given.
+    #[clap(long)]
+    resume: Option<PathBuf>,
 }
 
+/// Width of the image when neither the command line nor the scene gives one.
+const DEFAULT_WIDTH: usize = 400;
+
 /// This project is in following with Peter Shirley's excellent Ray Tracing in a Weekend book. 
 fn main() -> Result<()> {
     // CLI
     let cli = Cli::parse();
 
-    // RNG
-    // The camera has to live for as long as rendering takes, and we need random numbers
-    // both for some camera functionality and for processing the ray after the camera
-    // spits one out. Thus, we need to have two separate threads for the RNG. We take the
-    // approach of passing around mutable references to `ThreadRng` structs from the `rand`
-    // crate as they claim that this is a bit faster than spawning a new `ThreadRng` process
-    // implicitly with calls to `random` since we need random numbers quite often. Here, we
-    // make an additional `ThreadRng` for randomly generating the scene.
-    let mut world_rng = rand::thread_rng();
+    // Resume
+    let resumed = cli.resume.as_ref().map(|path| Accumulation::load(path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }));
 
-    // Image
-    let width = cli.width.unwrap_or(400);
-    let height = cli.height.unwrap_or((width as f64 * 9.0 / 16.0) as usize);
-    let samples = cli.samples.unwrap_or(100);
-    let depth = cli.depth.unwrap_or(32);
-    let aspect_ratio = width as f64 / height as f64;
+    // RNG
+    // Every random number comes from a seeded generator so that a render can be
+    // reproduced exactly. The scene gets one generator for the whole world, while
+    // each sample of each pixel gets its own, derived from the seed and the pixel
+    // and sample numbers, so the result doesn't depend on which thread happens to
+    // render which row or on how the samples are split into passes.
+    let seed = cli.seed.or(resumed.as_ref().map(|accum| accum.seed)).unwrap_or_else(|| {
+        let seed = rand::thread_rng().gen();
+        eprintln!("seed: {}", seed);
+        seed
+    });
+    let mut world_rng = RenderRng::seed_from_u64(seed);
 
     // World
-    let world = random_scene(&mut world_rng);
-
-    // Camera
-    // TODO: It would be neat to be able to specify these in someway to describe a series
-    // of different scenes. I think this is beyond a tasteful CLI though, so it'll have 
-    // to wait on me writing a TOML or JSON scene descripter with serde.
-    const VFOV: f64 = 20.0;
-    let lookfrom = Point::new(13.0, 2.0, 3.0);
-    let lookat = Point::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
+    let mut scene = match &cli.scene {
+        Some(path) => Scene::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+        None => {
+            let mut scene = Scene::new(CameraDesc::default());
+            scene.world = random_scene(&mut world_rng);
+            scene
+        }
+    };
+    if let Some(spec) = &cli.background {
+        scene.background = Background::parse(spec).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+    }
 
+    // Image
+    let image = &scene.image;
+    let width = cli.width.or(image.width).unwrap_or(DEFAULT_WIDTH);
+    // Overriding only the width keeps the aspect ratio the scene file would
+    // have rendered at, counting the default width if it only sets a height.
+    let height = cli.height.unwrap_or(match (cli.width, image.height) {
+        (Some(w), Some(scene_h)) => (w * scene_h / image.width.unwrap_or(DEFAULT_WIDTH)).max(1),
+        (None, Some(scene_h)) => scene_h,
+        _ => (width as f64 * 9.0 / 16.0) as usize,
+    });
+    let samples = cli.samples.or(image.samples).unwrap_or(100);
+    let depth = cli.depth.or(image.depth).unwrap_or(32);
 
     // Render 
-    let filename = cli.out.unwrap_or("image.ppm".to_string());
-    let mut file = File::create(filename).unwrap();
-    
-    file.write_all("P3\n".as_bytes())?;
-    file.write_all(format!("{} {}\n", width, height).as_bytes())?;
-    file.write_all("255\n".as_bytes())?;
-
-    // trying to rewrite in parallel
-    let mut pixels = vec![0; width * height * 3];
-
-    let bands: Vec<(usize, &mut [u8])> = pixels.chunks_mut(width * 3).enumerate().collect();
-    bands.into_par_iter().progress_count(height as u64).for_each(|(j, band)| {
-        let mut rng = rand::thread_rng();
-        // Only needs to be mutable for the RNG to work.
-        let mut camera = Camera::new(aspect_ratio, VFOV, lookfrom, lookat, vup, aperture, dist_to_focus); 
-        for i in (0..width).rev() {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-            for _s in 0..samples {
-                let u = (i as f64 + rng.gen::<f64>()) / (width as f64 - 1.0);
-                let v = (j as f64 + rng.gen::<f64>()) / (height as f64 - 1.0);
-                let ray = camera.get_ray(u, v);
-                pixel_color += ray_color(&ray, &world, depth, &mut rng);
+    // Output format comes from the extension, so check it before spending any
+    // time rendering.
+    let filename = PathBuf::from(cli.out.unwrap_or("image.ppm".to_string()));
+    if let Err(e) = ImageFormat::from_path(&filename) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let renderer = Renderer::new(scene, RenderSettings { width, height, samples, depth, seed, integrator: cli.integrator });
+    if cli.integrator == Integrator::Mis && renderer.lights().unsampled() > 0 {
+        eprintln!("warning: {} emissive object(s) can't be sampled directly and will only be found by bouncing",
+                  renderer.lights().unsampled());
+    }
+    let mut accum = match resumed {
+        Some(mut accum) => {
+            if (accum.width, accum.height) != (width, height) {
+                eprintln!("can't resume a {}x{} render at {}x{}", accum.width, accum.height, width, height);
+                std::process::exit(1);
             }
-            let pix_result = render::write_color_to_pixel_buffer(pixel_color, samples);
-            band[i * 3] = pix_result.0;
-            band[i * 3 + 1] = pix_result.1;
-            band[i * 3 + 2] = pix_result.2;
+            accum.seed = seed;
+            accum
         }
-    });
+        None => renderer.accumulation(),
+    };
+
+    // Without --pass-samples everything happens in one pass, written at the end.
+    let pass_samples = cli.pass_samples.unwrap_or(samples).max(1);
+    let passes = samples.saturating_sub(accum.samples).div_ceil(pass_samples);
+    let progress = ProgressBar::new((passes * height) as u64);
+    let checkpoint_interval = Duration::from_secs_f64(cli.checkpoint_secs.unwrap_or(0.0));
+    let mut last_checkpoint = Instant::now();
+
+    while accum.samples < samples {
+        let pass = pass_samples.min(samples - accum.samples);
+        renderer.render_pass_with(&mut accum, pass, || progress.inc(1));
+
+        if accum.samples < samples && last_checkpoint.elapsed() >= checkpoint_interval {
+            checkpoint(&filename, cli.accum.as_deref(), &accum)?;
+            last_checkpoint = Instant::now();
+        }
+    }
+    progress.finish();
 
-    render::write_buffer(&mut file, &pixels)?;
+    checkpoint(&filename, cli.accum.as_deref(), &accum)?;
 
     Ok(())
 }
 
-/// This generates a random scene using the same business logic as for the scene on the cover of the book.
-/// We make several different small spheres, somewhat randomly positioning them and assigning them a material.
-fn random_scene(rng: &mut ThreadRng) -> HitList<MatKind> {
-    let mut world: HitList<MatKind> = HitList::new();
-    
-    let ground = MatKind::Lambertian(Lambertian::new(Color::new(0.5117, 0.2539, 0.0977)));
-    world.push(Hittable::Sphere(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, ground)));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen::<f64>();
-            let center = Point::new(a as f64 + 0.9 * rng.gen::<f64>(), 0.2, b as f64 + 0.9 * rng.gen::<f64>());
-
-            if (center - Point::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let sphere_mat: MatKind;
-                if choose_mat < 0.6 {
-                    // diffuse
-                    let albedo = Color::random(0.0, 1.0, rng) * Color::random(0.0, 1.0, rng);
-                    sphere_mat = MatKind::Lambertian(Lambertian::new(albedo));
-                    world.push(Hittable::Sphere(Sphere::new(center, 0.2, sphere_mat)));
-                } else if choose_mat < 0.8 {
-                    // metal
-                    let albedo = Color::random(0.2, 1.0, rng);
-                    sphere_mat = MatKind::Metal(Metal::new(albedo));
-                    world.push(Hittable::Sphere(Sphere::new(center, 0.2, sphere_mat)));
-                } else {
-                    // glass
-                    sphere_mat = MatKind::Dielectric(Dielectric::new(1.5));
-                    world.push(Hittable::Sphere(Sphere::new(center, 0.2, sphere_mat)));
-                }
-            }
-        }
+/// Writes the image so far, and the accumulation buffer if asked to.
+fn checkpoint(filename: &Path, accum_path: Option<&Path>, accum: &Accumulation) -> Result<()> {
+    accum.image().save(filename)?;
+    if let Some(path) = accum_path {
+        accum.save(path)?;
     }
-
-    let mat1 = MatKind::Dielectric(Dielectric::new(1.5));
-    world.push(Hittable::Sphere(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, mat1)));
-
-    let mat2 = MatKind::Lambertian(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.push(Hittable::Sphere(Sphere::new(Point::new(-4.0, 1.0, 0.0), 1.0, mat2)));
-
-    let mat3 = MatKind::Metal(Metal::new(Color::new(0.7, 0.6, 0.5)));
-    world.push(Hittable::Sphere(Sphere::new(Point::new(4.0, 1.0, 0.0), 1.0, mat3)));
-
-    world
+    Ok(())
 }
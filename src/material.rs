@@ -1,8 +1,10 @@
 use rand::prelude::*;
 
 use crate::hit::HitRecord;
+use crate::microfacet::{schlick, to_world, Ggx};
 use crate::ray::Ray;
-use crate::render::Color;
+use crate::render::{Color, PI};
+use crate::texture::Texture;
 use crate::vec::Vec3;
 
 pub struct Scatter {
@@ -11,76 +13,200 @@ pub struct Scatter {
     pub scattered: Ray,
 }
 
+/// A direction picked by `Material::sample`.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    /// Direction the light comes in from, pointing away from the surface.
+    pub wi: Vec3,
+    /// BSDF times cosine over the pdf: what a path's throughput gets multiplied by.
+    pub weight: Color,
+    /// Solid angle density `wi` was picked with. Zero for specular samples,
+    /// which come from a delta distribution and have no density.
+    pub pdf: f64,
+    pub is_specular: bool,
+}
+
+/// Directions passed to the BSDF methods are world space unit vectors that both
+/// point away from the hit point: `wo` back toward where the ray came from and
+/// `wi` toward where light arrives from. The hemisphere is the one
+/// `hit_record.normal` points into.
 pub trait Material {
-    fn scatter(&self,
-               r_in: &Ray, 
-               hit_record: &HitRecord<MatKind>, 
-               rng: &mut ThreadRng) -> Scatter;
+    /// Picks an incoming direction, or `None` if the surface absorbs the ray.
+    /// Works with any generator so seeded, quasi-random or scripted test
+    /// sequences can be swapped in; the renderer itself uses `RenderRng`.
+    fn sample<R: Rng + ?Sized>(&self,
+              wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>;
+
+    /// The BSDF for light arriving along `wi` and leaving along `wo`, without
+    /// the cosine term. Specular surfaces have nothing to evaluate and give black.
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, _hit_record: &HitRecord<MatKind>) -> Color {
+        Color::default()
+    }
+
+    /// Density with which `sample` would pick `wi`. Zero for specular surfaces.
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _hit_record: &HitRecord<MatKind>) -> f64 {
+        0.0
+    }
+
+    /// The book's interface, built on `sample`: where `r_in` goes next and how
+    /// much it is attenuated on the way.
+    fn scatter<R: Rng + ?Sized>(&self,
+               r_in: &Ray,
+               hit_record: &HitRecord<MatKind>,
+               rng: &mut R) -> Scatter
+    {
+        match self.sample(&-r_in.dir.unit_vector(), hit_record, rng) {
+            Some(sample) => Scatter {
+                is_scattered: true,
+                attenuation: sample.weight,
+                scattered: Ray::with_time(hit_record.p, sample.wi, r_in.time),
+            },
+            None => Scatter { is_scattered: false, attenuation: Color::default(), scattered: *r_in },
+        }
+    }
+
+    /// Fraction of the light that survives `distance` through the interior of
+    /// the object to reach this hit from the inside. Only absorbing dielectrics
+    /// take anything away; everything else is hollow as far as light cares.
+    fn transmittance(&self, _hit_record: &HitRecord<MatKind>, _distance: f64) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    /// Light given off by the surface at the hit point. Only lights emit, so
+    /// everything else gets black for free.
+    fn emitted(&self, _hit_record: &HitRecord<MatKind>) -> Color {
+        Color::default()
+    }
+
+    /// The normal to shade the hit with, on the same side as
+    /// `hit_record.normal`. Only `Bumped` materials bend it; the integrators
+    /// put it in the record before calling anything else.
+    fn shading_normal(&self, hit_record: &HitRecord<MatKind>) -> Vec3 {
+        hit_record.normal
+    }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Texture,
 }
 
 impl Lambertian {
-    pub fn new(albedo: Color) -> Self {
-        Lambertian { albedo }
+    pub fn new<T: Into<Texture>>(albedo: T) -> Self {
+        Lambertian { albedo: albedo.into() }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, 
-               _r_in: &Ray, 
-               hit_record: &HitRecord<MatKind>, 
-               rng: &mut ThreadRng) -> Scatter 
+    /// Cosine weighted about the normal, so the cosine and the pdf cancel and
+    /// the weight is just the albedo.
+    fn sample<R: Rng + ?Sized>(&self,
+              _wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>
     {
-        let mut scatter_direction = hit_record.normal + Vec3::random_unit_vector(rng);
+        let (w, u, v) = hit_record.normal.coordinate_system_from();
+        let d = Vec3::random_cosine_direction(rng);
+        let wi = u * d.x + v * d.y + w * d.z;
+        let pdf = d.z / PI;
+        if pdf <= 0.0 { return None }
+
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+        Some(BsdfSample { wi, weight: albedo, pdf, is_specular: false })
+    }
 
-        if scatter_direction.near_zero() { scatter_direction = hit_record.normal; }
-        
-        let scattered = Ray::new(hit_record.p, scatter_direction);
-        let attenuation = self.albedo;
-        let is_scattered = true;
-        
-        Scatter { is_scattered, attenuation, scattered }
+    fn eval(&self, _wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> Color {
+        if wi.dot(&hit_record.normal) <= 0.0 { return Color::default() }
+        self.albedo.value(hit_record.u, hit_record.v, &hit_record.p) / PI
+    }
+
+    fn pdf(&self, _wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> f64 {
+        let cos = wi.dot(&hit_record.normal);
+        if cos <= 0.0 { 0.0 } else { cos / PI }
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+/// A conductor. With zero roughness it is the book's perfect mirror; anything
+/// rougher (the book's `fuzz`) spreads the reflection out with a GGX microfacet
+/// distribution, going from satin toward brushed and matte metal as it nears
+/// one. The albedo is the reflectance at normal incidence, rising to white at
+/// grazing angles.
+#[derive(Clone, Debug, Default)]
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: Texture,
+    pub roughness: f64,
 }
 
 impl Metal {
-    pub fn new(albedo: Color) -> Self {
-        Metal { albedo }
+    /// Below this the distribution is too sharp to sample usefully and the
+    /// surface is treated as a mirror.
+    const MIRROR_ROUGHNESS: f64 = 1.0e-3;
+
+    pub fn new<T: Into<Texture>>(albedo: T, roughness: f64) -> Self {
+        Metal { albedo: albedo.into(), roughness: roughness.clamp(0.0, 1.0) }
+    }
+
+    fn is_mirror(&self) -> bool {
+        self.roughness < Self::MIRROR_ROUGHNESS
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, 
-               r_in: &Ray, 
-               hit_record: &HitRecord<MatKind>, 
-               _rng: &mut ThreadRng) -> Scatter
+    fn sample<R: Rng + ?Sized>(&self,
+              wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>
     {
-        let reflected = r_in.dir.unit_vector().reflect(hit_record.normal);
-        let scattered = Ray::new(hit_record.p, reflected);
-        let attenuation = self.albedo;
-        let is_scattered = scattered.dir.dot(&hit_record.normal) > 0.0;
-        
-        Scatter { is_scattered, attenuation, scattered }
+        let n = hit_record.normal;
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+
+        if self.is_mirror() {
+            let wi = (-*wo).reflect(n);
+            if wi.dot(&n) <= 0.0 { return None }
+            return Some(BsdfSample { wi, weight: albedo, pdf: 0.0, is_specular: true })
+        }
+
+        let ggx = Ggx::from_roughness(self.roughness);
+        let wi = ggx.sample_reflection(wo, &n, rng)?;
+        let pdf = ggx.pdf_reflection(wo, &wi, &n);
+        if pdf <= 0.0 { return None }
+        let weight = ggx.eval_reflection(albedo, wo, &wi, &n) * (wi.dot(&n) / pdf);
+        Some(BsdfSample { wi, weight, pdf, is_specular: false })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> Color {
+        if self.is_mirror() { return Color::default() }
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+        Ggx::from_roughness(self.roughness).eval_reflection(albedo, wo, wi, &hit_record.normal)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> f64 {
+        if self.is_mirror() { return 0.0 }
+        Ggx::from_roughness(self.roughness).pdf_reflection(wo, wi, &hit_record.normal)
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+/// Glass, water and the like. `tint` colors the light passing through the
+/// surface, for thin colored glass; `absorption` is the Beer–Lambert
+/// coefficient of the interior per unit length, so thick parts come out darker
+/// and more saturated than thin ones, as with liquids and solid colored glass.
+#[derive(Clone, Copy, Debug)]
 pub struct Dielectric {
     pub ir: f64,
+    pub tint: Color,
+    pub absorption: Color,
 }
 
 impl Dielectric {
+    /// Clear glass: no tint, no absorption.
     pub fn new(ir: f64) -> Self {
-        Dielectric { ir }
+        Dielectric::tinted(ir, Color::new(1.0, 1.0, 1.0), Color::default())
+    }
+
+    pub fn tinted(ir: f64, tint: Color, absorption: Color) -> Self {
+        Dielectric { ir, tint, absorption }
     }
 
     pub fn reflectance(cos: f64, ref_idx: f64) -> f64 {
@@ -91,49 +217,493 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self,
-               r_in: &Ray,
-               hit_record: &HitRecord<MatKind>,
-               rng: &mut ThreadRng) -> Scatter
+    fn sample<R: Rng + ?Sized>(&self,
+              wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>
     {
-        let attenuation = Color::new(0.98, 0.98, 0.98);
-        let refraction_ratio: f64;
-        if hit_record.front_face.unwrap() { refraction_ratio = 1.0 / self.ir; }
-        else { refraction_ratio = self.ir; }
+        let refraction_ratio = if hit_record.front_face.unwrap() { 1.0 / self.ir } else { self.ir };
 
-        let unit_dir = r_in.dir.unit_vector();
+        let unit_dir = -*wo;
         let cos_theta: f64 = -unit_dir.dot(&hit_record.normal).min(1.0);
         let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let dir: Vec3;
-        if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>() { dir = unit_dir.reflect(hit_record.normal); }
-        else { dir = unit_dir.refract(hit_record.normal, refraction_ratio); }
+        let (wi, weight) = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>() {
+            (unit_dir.reflect(hit_record.normal), Color::new(1.0, 1.0, 1.0))
+        } else {
+            (unit_dir.refract(hit_record.normal, refraction_ratio), self.tint)
+        };
 
-        let scattered = Ray::new(hit_record.p, dir);
-        let is_scattered = true;
+        Some(BsdfSample { wi, weight, pdf: 0.0, is_specular: true })
+    }
 
-        Scatter { is_scattered, attenuation, scattered }
+    fn transmittance(&self, hit_record: &HitRecord<MatKind>, distance: f64) -> Color {
+        if hit_record.front_face.unwrap_or(true) { return Color::new(1.0, 1.0, 1.0) }
+        let a = self.absorption * distance;
+        Color::new((-a.x).exp(), (-a.y).exp(), (-a.z).exp())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Default for Dielectric {
+    fn default() -> Self {
+        Dielectric::new(1.5)
+    }
+}
+
+/// One material for everything, after the Disney/Blender "principled" BSDF, so
+/// that assets from other tools map onto a single set of parameters. From the
+/// top down it is layered as:
+///
+/// - an optional clear coat, a colorless GGX lobe with its own roughness,
+/// - a `metallic` blend between a conductor (GGX tinted by the base color) and
+///   a dielectric,
+/// - where the dielectric is a GGX specular lobe (`specular` scales its
+///   reflectance, 0.5 being the usual 4%) over a diffuse base, or, as much as
+///   `transmission` asks for, smooth glass of index `ior` tinted by the base
+///   color.
+///
+/// Whatever a layer reflects never reaches the ones below it, which keeps the
+/// total at or below what came in. Transmission ignores roughness for now.
+#[derive(Clone, Debug)]
+pub struct Principled {
+    pub base_color: Texture,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub transmission: f64,
+    pub ior: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub emission: Color,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            base_color: Texture::Solid(Color::new(0.8, 0.8, 0.8)),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            transmission: 0.0,
+            ior: 1.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            emission: Color::default(),
+        }
+    }
+}
+
+/// How much each lobe of a `Principled` contributes at a given hit, along with
+/// the odds of sampling it.
+struct Lobes {
+    base: Color,
+    /// Energy left after the clear coat takes its share.
+    below_coat: f64,
+    /// Reflectance of the dielectric specular layer straight on.
+    dielectric_f0: Color,
+    /// Energy left under the dielectric specular layer, for the diffuse base.
+    below_specular: f64,
+    p_coat: f64,
+    p_specular: f64,
+    p_diffuse: f64,
+    p_glass: f64,
+}
+
+const COAT_F0: f64 = 0.04;
+
+impl Principled {
+    fn lobes(&self, wo: &Vec3, hit_record: &HitRecord<MatKind>) -> Lobes {
+        let base = self.base_color.value(hit_record.u, hit_record.v, &hit_record.p);
+        let cos_o = wo.dot(&hit_record.normal).max(0.0);
+        let grey = |c: f64| Color::new(c, c, c);
+
+        let coat = self.clearcoat * schlick(grey(COAT_F0), cos_o).x;
+        let below_coat = 1.0 - coat;
+        let dielectric_f0 = grey(0.08 * self.specular);
+        let dielectric_fresnel = schlick(dielectric_f0, cos_o).x;
+        let below_specular = 1.0 - dielectric_fresnel;
+
+        let dielectric = below_coat * (1.0 - self.metallic);
+        let opaque = dielectric * (1.0 - self.transmission);
+        let w_coat = coat;
+        let w_specular = below_coat * self.metallic + opaque * dielectric_fresnel;
+        let w_diffuse = opaque * below_specular * luminance(&base);
+        let w_glass = dielectric * self.transmission;
+
+        let total = w_coat + w_specular + w_diffuse + w_glass;
+        let p = |w: f64| if total > 0.0 { w / total } else { 0.0 };
+        Lobes {
+            base,
+            below_coat,
+            dielectric_f0,
+            below_specular,
+            p_coat: p(w_coat),
+            p_specular: p(w_specular),
+            p_diffuse: p(w_diffuse),
+            p_glass: p(w_glass),
+        }
+    }
+
+    fn ggx(&self) -> Ggx {
+        Ggx::from_roughness(self.roughness)
+    }
+
+    fn coat_ggx(&self) -> Ggx {
+        Ggx::from_roughness(self.clearcoat_roughness)
+    }
+
+    /// Everything but the glass, which is a delta lobe and only ever sampled.
+    fn eval_lobes(&self, lobes: &Lobes, wo: &Vec3, wi: &Vec3, n: &Vec3) -> Color {
+        if wi.dot(n) <= 0.0 { return Color::default() }
+
+        let coat = self.coat_ggx().eval_reflection(Color::new(COAT_F0, COAT_F0, COAT_F0), wo, wi, n) * self.clearcoat;
+        let metal = self.ggx().eval_reflection(lobes.base, wo, wi, n) * self.metallic;
+        let opaque = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let specular = self.ggx().eval_reflection(lobes.dielectric_f0, wo, wi, n);
+        let diffuse = lobes.base * (lobes.below_specular / PI);
+
+        coat + (metal + (specular + diffuse) * opaque) * lobes.below_coat
+    }
+
+    fn pdf_lobes(&self, lobes: &Lobes, wo: &Vec3, wi: &Vec3, n: &Vec3) -> f64 {
+        let cos_i = wi.dot(n);
+        if cos_i <= 0.0 { return 0.0 }
+        lobes.p_coat * self.coat_ggx().pdf_reflection(wo, wi, n)
+            + lobes.p_specular * self.ggx().pdf_reflection(wo, wi, n)
+            + lobes.p_diffuse * cos_i / PI
+    }
+}
+
+impl Material for Principled {
+    /// Picks one lobe by its share of the energy. The glass is a delta lobe and
+    /// returned as a specular sample; the others are weighted by the density of
+    /// the whole mixture so every lobe that could have produced the direction
+    /// is accounted for.
+    fn sample<R: Rng + ?Sized>(&self,
+              wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>
+    {
+        let n = hit_record.normal;
+        let lobes = self.lobes(wo, hit_record);
+        let mut pick = rng.gen::<f64>();
+
+        if pick < lobes.p_glass {
+            // Only the light going through is colored by the base; what the
+            // surface of the glass reflects stays white, as with `Dielectric`.
+            let glass = Dielectric::new(self.ior).sample(wo, hit_record, rng)?;
+            let tint = if glass.wi.dot(&n) < 0.0 { lobes.base } else { Color::new(1.0, 1.0, 1.0) };
+            let weight = tint * ((1.0 - self.metallic) * self.transmission * lobes.below_coat / lobes.p_glass);
+            return Some(BsdfSample { wi: glass.wi, weight, pdf: 0.0, is_specular: true })
+        }
+        pick -= lobes.p_glass;
+
+        let wi = if pick < lobes.p_coat {
+            self.coat_ggx().sample_reflection(wo, &n, rng)?
+        } else if pick < lobes.p_coat + lobes.p_specular {
+            self.ggx().sample_reflection(wo, &n, rng)?
+        } else {
+            to_world(&Vec3::random_cosine_direction(rng), &n)
+        };
+
+        let pdf = self.pdf_lobes(&lobes, wo, &wi, &n);
+        if pdf <= 0.0 { return None }
+        let weight = self.eval_lobes(&lobes, wo, &wi, &n) * (wi.dot(&n) / pdf);
+        Some(BsdfSample { wi, weight, pdf, is_specular: false })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> Color {
+        let lobes = self.lobes(wo, hit_record);
+        self.eval_lobes(&lobes, wo, wi, &hit_record.normal)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> f64 {
+        let lobes = self.lobes(wo, hit_record);
+        self.pdf_lobes(&lobes, wo, wi, &hit_record.normal)
+    }
+
+    fn emitted(&self, _hit_record: &HitRecord<MatKind>) -> Color {
+        self.emission
+    }
+}
+
+#[inline]
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// The phase function of a participating medium: light scattered inside it
+/// leaves in any direction with equal probability. There is no surface, so
+/// there is no cosine either; see `ConstantMedium`.
+#[derive(Clone, Debug, Default)]
+pub struct Isotropic {
+    pub albedo: Texture,
+}
+
+impl Isotropic {
+    pub fn new<T: Into<Texture>>(albedo: T) -> Self {
+        Isotropic { albedo: albedo.into() }
+    }
+}
+
+impl Material for Isotropic {
+    fn sample<R: Rng + ?Sized>(&self,
+              _wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>
+    {
+        let wi = Vec3::random_unit_vector(rng);
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.p);
+        Some(BsdfSample { wi, weight: albedo, pdf: 1.0 / (4.0 * PI), is_specular: false })
+    }
+
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, hit_record: &HitRecord<MatKind>) -> Color {
+        self.albedo.value(hit_record.u, hit_record.v, &hit_record.p) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _hit_record: &HitRecord<MatKind>) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// A light source. It absorbs everything that hits it and emits `emit` from
+/// both sides of the surface; `emit` is radiance, so values above one are the
+/// norm for lights that have to illuminate a whole room.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample<R: Rng + ?Sized>(&self,
+              _wo: &Vec3,
+              _hit_record: &HitRecord<MatKind>,
+              _rng: &mut R) -> Option<BsdfSample>
+    {
+        None
+    }
+
+    fn emitted(&self, _hit_record: &HitRecord<MatKind>) -> Color {
+        self.emit
+    }
+}
+
+/// Where a `Bumped` material gets its normals from.
+#[derive(Clone, Debug)]
+pub enum NormalMap {
+    /// A tangent space normal map: red, green and blue, remapped from [0, 1]
+    /// to [-1, 1], are the normal's components along the tangent, the
+    /// bitangent and the normal, so green points up the texture (the OpenGL
+    /// convention). Images holding normal maps should be loaded raw.
+    Normal(Texture),
+    /// A bump map: the surface is pushed out by the brightness of `height`
+    /// times `scale`, in world units, with the normal following along. Slopes
+    /// are taken across the surface itself, going by how far the shape says
+    /// a step in `u` or `v` is, so an image bumps an object as deeply whatever
+    /// its size. The height is read a texel to either side for images and a
+    /// short step along the surface for everything else, so solid textures
+    /// like noise make good bumps too.
+    Height { height: Texture, scale: f64 },
+}
+
+/// Distance along the surface for the slope of a bump map that isn't an image.
+const BUMP_STEP: f64 = 1.0e-3;
+
+impl NormalMap {
+    /// The perturbed normal on the outside of the surface at the hit.
+    fn outward_normal(&self, hit_record: &HitRecord<MatKind>) -> Vec3 {
+        let ((t, b), n) = (hit_record.tangent_frame(), hit_record.outward_normal());
+        match self {
+            Self::Normal(texture) => {
+                let c = texture.value(hit_record.u, hit_record.v, &hit_record.p);
+                t * (2.0 * c.x - 1.0) + b * (2.0 * c.y - 1.0) + n * (2.0 * c.z - 1.0)
+            }
+            Self::Height { height, scale } => {
+                // How the point moves with `u` and `v`, flattened onto the
+                // shading plane. Shapes that can't say get the tangent frame,
+                // taking a unit of `u` or `v` to be a unit of distance.
+                let in_plane = |d: Vec3| d - n * n.dot(&d);
+                let (pu, pv) = (in_plane(hit_record.dpdu), in_plane(hit_record.dpdv));
+                let spans = pu.cross(&pv).length_squared() > 1.0e-12 * pu.length_squared() * pv.length_squared();
+                let (pu, pv) = if spans { (pu, pv) } else { (t, b) };
+                let (uu, uv, vv) = (pu.dot(&pu), pu.dot(&pv), pv.dot(&pv));
+                let det = uu * vv - uv * uv;
+
+                // Offsetting both the texture coordinates and the point works
+                // for either kind of texture, since each ignores the other.
+                let (du, dv) = match height {
+                    Texture::Image(image) => (1.0 / image.width() as f64, 1.0 / image.height() as f64),
+                    _ => (BUMP_STEP / uu.sqrt(), BUMP_STEP / vv.sqrt()),
+                };
+                let h = |du: f64, dv: f64| {
+                    let p = hit_record.p + pu * du + pv * dv;
+                    luminance(&height.value(hit_record.u + du, hit_record.v + dv, &p))
+                };
+                let slope_u = (h(du, 0.0) - h(-du, 0.0)) / (2.0 * du);
+                let slope_v = (h(0.0, dv) - h(0.0, -dv)) / (2.0 * dv);
+                // The height's gradient across the surface is the vector in the
+                // plane whose components along `pu` and `pv` are those slopes.
+                let gradient = (pu * (vv * slope_u - uv * slope_v) + pv * (uu * slope_v - uv * slope_u)) / det;
+                n - gradient * *scale
+            }
+        }
+    }
+}
+
+/// Another material with its shading normals taken from a `NormalMap`, for
+/// surface detail too fine to model. Only the shading changes: silhouettes and
+/// shadows still follow the geometry.
+#[derive(Clone, Debug)]
+pub struct Bumped {
+    pub material: MatKind,
+    pub map: NormalMap,
+}
+
+impl Bumped {
+    pub fn new(material: MatKind, map: NormalMap) -> Self {
+        Bumped { material, map }
+    }
+}
+
+impl Material for Bumped {
+    fn sample<R: Rng + ?Sized>(&self,
+              wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>
+    {
+        self.material.sample(wo, hit_record, rng)
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> Color {
+        self.material.eval(wo, wi, hit_record)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> f64 {
+        self.material.pdf(wo, wi, hit_record)
+    }
+
+    fn transmittance(&self, hit_record: &HitRecord<MatKind>, distance: f64) -> Color {
+        self.material.transmittance(hit_record, distance)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord<MatKind>) -> Color {
+        self.material.emitted(hit_record)
+    }
+
+    /// Falls back on the geometry's normal for volumes, which have no surface
+    /// to bump, and where the map would turn the normal away from the side
+    /// that was hit.
+    fn shading_normal(&self, hit_record: &HitRecord<MatKind>) -> Vec3 {
+        let front = match hit_record.front_face {
+            Some(front) => front,
+            None => return hit_record.normal,
+        };
+        let n = self.map.outward_normal(hit_record);
+        let n = if front { n } else { -n };
+        if n.dot(&hit_record.normal) <= 0.0 || n.near_zero() { return hit_record.normal }
+        n.unit_vector()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum MatKind {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
+    DiffuseLight(DiffuseLight),
+    Principled(Principled),
+    Isotropic(Isotropic),
+    Bumped(Box<Bumped>),
+}
+
+impl MatKind {
+    /// Whether surfaces made of this give off any light.
+    pub fn is_emissive(&self) -> bool {
+        match self {
+            Self::DiffuseLight(l) => !l.emit.near_zero(),
+            Self::Principled(p) => !p.emission.near_zero(),
+            Self::Bumped(b) => b.material.is_emissive(),
+            _ => false,
+        }
+    }
 }
 
 impl Material for MatKind {
-    fn scatter(&self, 
-               r_in: &Ray, 
-               hit_record: &HitRecord<MatKind>, 
-               rng: &mut ThreadRng) -> Scatter
+    fn sample<R: Rng + ?Sized>(&self,
+              wo: &Vec3,
+              hit_record: &HitRecord<MatKind>,
+              rng: &mut R) -> Option<BsdfSample>
     {
         match self {
-            Self::Lambertian(l) => l.scatter(r_in, hit_record, rng),
-            Self::Metal(m) => m.scatter(r_in, hit_record, rng),
-            Self::Dielectric(d) => d.scatter(r_in, hit_record, rng),
+            Self::Lambertian(l) => l.sample(wo, hit_record, rng),
+            Self::Metal(m) => m.sample(wo, hit_record, rng),
+            Self::Dielectric(d) => d.sample(wo, hit_record, rng),
+            Self::DiffuseLight(l) => l.sample(wo, hit_record, rng),
+            Self::Principled(p) => p.sample(wo, hit_record, rng),
+            Self::Isotropic(i) => i.sample(wo, hit_record, rng),
+            Self::Bumped(b) => b.sample(wo, hit_record, rng),
+        }
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> Color {
+        match self {
+            Self::Lambertian(l) => l.eval(wo, wi, hit_record),
+            Self::Metal(m) => m.eval(wo, wi, hit_record),
+            Self::Dielectric(d) => d.eval(wo, wi, hit_record),
+            Self::DiffuseLight(l) => l.eval(wo, wi, hit_record),
+            Self::Principled(p) => p.eval(wo, wi, hit_record),
+            Self::Isotropic(i) => i.eval(wo, wi, hit_record),
+            Self::Bumped(b) => b.eval(wo, wi, hit_record),
+        }
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord<MatKind>) -> f64 {
+        match self {
+            Self::Lambertian(l) => l.pdf(wo, wi, hit_record),
+            Self::Metal(m) => m.pdf(wo, wi, hit_record),
+            Self::Dielectric(d) => d.pdf(wo, wi, hit_record),
+            Self::DiffuseLight(l) => l.pdf(wo, wi, hit_record),
+            Self::Principled(p) => p.pdf(wo, wi, hit_record),
+            Self::Isotropic(i) => i.pdf(wo, wi, hit_record),
+            Self::Bumped(b) => b.pdf(wo, wi, hit_record),
+        }
+    }
+
+    fn transmittance(&self, hit_record: &HitRecord<MatKind>, distance: f64) -> Color {
+        match self {
+            Self::Lambertian(l) => l.transmittance(hit_record, distance),
+            Self::Metal(m) => m.transmittance(hit_record, distance),
+            Self::Dielectric(d) => d.transmittance(hit_record, distance),
+            Self::DiffuseLight(l) => l.transmittance(hit_record, distance),
+            Self::Principled(p) => p.transmittance(hit_record, distance),
+            Self::Isotropic(i) => i.transmittance(hit_record, distance),
+            Self::Bumped(b) => b.transmittance(hit_record, distance),
+        }
+    }
+
+    fn emitted(&self, hit_record: &HitRecord<MatKind>) -> Color {
+        match self {
+            Self::Lambertian(l) => l.emitted(hit_record),
+            Self::Metal(m) => m.emitted(hit_record),
+            Self::Dielectric(d) => d.emitted(hit_record),
+            Self::DiffuseLight(l) => l.emitted(hit_record),
+            Self::Principled(p) => p.emitted(hit_record),
+            Self::Isotropic(i) => i.emitted(hit_record),
+            Self::Bumped(b) => b.emitted(hit_record),
+        }
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord<MatKind>) -> Vec3 {
+        match self {
+            Self::Bumped(b) => b.shading_normal(hit_record),
+            _ => hit_record.normal,
         }
     }
 }
@@ -143,3 +713,202 @@ impl Default for MatKind {
         MatKind::Lambertian(Lambertian::default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdr::HdrImage;
+    use crate::hit::{Hit, HitList, Hittable};
+    use crate::quad::Quad;
+    use crate::render::Point;
+    use crate::sphere::Sphere;
+    use crate::texture::ImageTexture;
+
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+
+    const EPSILON: f64 = 1.0e-9;
+
+    /// A front face hit at the origin, on a surface facing +z.
+    fn record(material: &MatKind) -> HitRecord<'_, MatKind> {
+        HitRecord::new(Point::ORIGIN, Vec3::Z_HAT, 1.0, material, Some(true))
+    }
+
+    #[test]
+    fn lambertian_weight_is_eval_over_pdf() {
+        let material = MatKind::Lambertian(Lambertian::new(Color::new(0.9, 0.5, 0.1)));
+        let rec = record(&material);
+        let wo = Vec3::new(0.3, -0.2, 1.0).unit_vector();
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..1000 {
+            let sample = material.sample(&wo, &rec, &mut rng).unwrap();
+            assert!(!sample.is_specular);
+            assert!((sample.pdf - material.pdf(&wo, &sample.wi, &rec)).abs() < EPSILON);
+            let expected = material.eval(&wo, &sample.wi, &rec) * (sample.wi.dot(&rec.normal) / sample.pdf);
+            assert!((sample.weight - expected).length() < EPSILON, "{:?} != {:?}", sample.weight, expected);
+        }
+    }
+
+    #[test]
+    fn lambertian_pdf_integrates_to_one() {
+        // Averaging pdf / (1 / 4π) over uniformly picked directions estimates
+        // its integral over the sphere, all of which should be in the hemisphere.
+        let material = MatKind::Lambertian(Lambertian::default());
+        let rec = record(&material);
+        let wo = Vec3::Z_HAT;
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let n = 200_000;
+        let total: f64 = (0..n)
+            .map(|_| material.pdf(&wo, &Vec3::random_unit_vector(&mut rng), &rec) * 4.0 * PI)
+            .sum();
+        let integral = total / n as f64;
+        assert!((integral - 1.0).abs() < 0.01, "integral = {}", integral);
+    }
+
+    #[test]
+    fn specular_samples_have_no_density() {
+        let wo = Vec3::new(0.5, 0.0, 1.0).unit_vector();
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for material in [
+            MatKind::Metal(Metal::new(Color::new(0.9, 0.9, 0.9), 0.0)),
+            MatKind::Dielectric(Dielectric::new(1.5)),
+        ] {
+            let rec = record(&material);
+            for _ in 0..100 {
+                let sample = material.sample(&wo, &rec, &mut rng).unwrap();
+                assert!(sample.is_specular && sample.pdf == 0.0, "{:?}", material);
+                assert_eq!(material.pdf(&wo, &sample.wi, &rec), 0.0);
+                assert_eq!(material.eval(&wo, &sample.wi, &rec), Color::default());
+            }
+        }
+    }
+
+    #[test]
+    fn normal_maps_get_a_frame_where_the_shape_has_none() {
+        // Straight down onto the top of a sphere, where its texture coordinates
+        // pinch to a point and it gives no tangents, behind a quad that does.
+        // The quad is tried first, so this also checks its frame isn't left
+        // behind in the record once the sphere turns out to be closer.
+        let tilted = Texture::Solid(Color::new(0.75, 0.5, 1.0));
+        let material = MatKind::Bumped(Box::new(Bumped::new(MatKind::default(), NormalMap::Normal(tilted))));
+        let mut world = HitList::new();
+        world.push(Hittable::Quad(Quad::new(Point::new(-1.0, -2.0, -1.0), Vec3::Z_HAT, Vec3::X_HAT, material.clone())));
+        world.push(Hittable::Sphere(Sphere::new(Point::ORIGIN, 1.0, material.clone())));
+        let ray = Ray::new(Point::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut rec = HitRecord::empty();
+        assert!(world.hit(&ray, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.t - 4.0).abs() < EPSILON);
+        assert_eq!(rec.tangent, Vec3::default());
+
+        let (t, b) = rec.tangent_frame();
+        assert!((t.length() - 1.0).abs() < EPSILON && (b.length() - 1.0).abs() < EPSILON);
+        assert!(t.dot(&b).abs() < EPSILON && t.dot(&rec.normal).abs() < EPSILON && b.dot(&rec.normal).abs() < EPSILON);
+        let n = material.shading_normal(&rec);
+        let expected = (t * 0.5 + rec.normal).unit_vector();
+        assert!((n - expected).length() < EPSILON, "{:?} != {:?}", n, expected);
+    }
+
+    /// The geometric and the shading normal where `ray` hits `shape`.
+    fn normals(shape: Hittable<MatKind>, ray: &Ray) -> (Vec3, Vec3) {
+        let mut rec = HitRecord::empty();
+        assert!(shape.hit(ray, 0.001, f64::INFINITY, &mut rec));
+        (rec.normal, rec.material().shading_normal(&rec))
+    }
+
+    #[test]
+    fn flat_normal_maps_leave_the_normal_alone() {
+        let flat = NormalMap::Normal(Texture::Solid(Color::new(0.5, 0.5, 1.0)));
+        let material = MatKind::Bumped(Box::new(Bumped::new(MatKind::default(), flat)));
+        let ray = Ray::new(Point::new(1.0, 2.0, 5.0), Vec3::new(-1.2, -1.9, -5.0));
+        let (normal, n) = normals(Hittable::Sphere(Sphere::new(Point::ORIGIN, 1.0, material)), &ray);
+        assert!((n - normal).length() < EPSILON, "{:?} != {:?}", n, normal);
+    }
+
+    #[test]
+    fn height_ramps_tilt_the_normal_downhill_by_their_slope_across_the_surface() {
+        // Brightness rising from 0 to 1 across the image, on quads of
+        // different widths: the height rises by `scale` over each quad, so
+        // the wider it is the gentler the slope, and the normal leans away
+        // from the way it rises.
+        let width = 16;
+        let pixels = (0..width).map(|x| {
+            let c = x as f64 / width as f64;
+            Color::new(c, c, c)
+        });
+        let image = ImageTexture::new(HdrImage { width, height: 1, pixels: pixels.collect() });
+        let scale = 0.5;
+        let map = NormalMap::Height { height: Texture::Image(Arc::new(image)), scale };
+        let material = MatKind::Bumped(Box::new(Bumped::new(MatKind::default(), map)));
+        for size in [1.0, 4.0] {
+            let quad = Quad::new(Point::ORIGIN, Vec3::new(size, 0.0, 0.0), Vec3::Y_HAT, material.clone());
+            let ray = Ray::new(Point::new(0.5 * size, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let (_, n) = normals(Hittable::Quad(quad), &ray);
+            let expected = Vec3::new(-scale / size, 0.0, 1.0).unit_vector();
+            assert!((n - expected).length() < EPSILON, "size {}: {:?} != {:?}", size, n, expected);
+        }
+    }
+
+    #[test]
+    fn principled_white_furnace_loses_energy_but_never_gains_it() {
+        // Under uniform white light the radiance leaving a surface is its
+        // albedo, the average sample weight, which can't be more than one.
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let samples = 20_000;
+        for roughness in [0.05, 0.5, 1.0] {
+            for transmission in [0.0, 0.5, 1.0] {
+                for clearcoat in [0.0, 1.0] {
+                    let material = MatKind::Principled(Principled {
+                        base_color: Texture::Solid(Color::new(1.0, 1.0, 1.0)),
+                        metallic: 0.0,
+                        roughness,
+                        transmission,
+                        clearcoat,
+                        ..Principled::default()
+                    });
+                    let rec = record(&material);
+                    for cos_o in [1.0, 0.5, 0.1] {
+                        let wo = Vec3::new((1.0f64 - cos_o * cos_o).sqrt(), 0.0, cos_o);
+                        let total = (0..samples)
+                            .filter_map(|_| material.sample(&wo, &rec, &mut rng))
+                            .fold(Color::default(), |total, sample| total + sample.weight);
+                        let albedo = total / samples as f64;
+                        assert!(
+                            albedo.max_component() <= 1.02,
+                            "roughness {}, transmission {}, clearcoat {}, cos {}: {:?}",
+                            roughness, transmission, clearcoat, cos_o, albedo,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn principled_glass_tints_only_what_it_lets_through() {
+        let base = Color::new(0.2, 0.4, 0.8);
+        let material = MatKind::Principled(Principled {
+            base_color: Texture::Solid(base),
+            transmission: 1.0,
+            ..Principled::default()
+        });
+        let rec = record(&material);
+        // Grazing, so the glass reflects often enough to see both branches.
+        let wo = Vec3::new(0.995, 0.0, 0.1).unit_vector();
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let (mut reflected, mut refracted) = (0, 0);
+        for _ in 0..1000 {
+            let sample = material.sample(&wo, &rec, &mut rng).unwrap();
+            if !sample.is_specular { continue }
+            let w = sample.weight;
+            if sample.wi.dot(&rec.normal) > 0.0 {
+                assert!(w.x == w.y && w.y == w.z, "reflection tinted {:?}", w);
+                reflected += 1;
+            } else {
+                assert!(w.cross(&base).length() < EPSILON, "refraction not tinted {:?}", w);
+                refracted += 1;
+            }
+        }
+        assert!(reflected > 0 && refracted > 0);
+    }
+}
@@ -0,0 +1,181 @@
+use std::ops::Mul;
+
+use crate::render::{degrees_to_radians, Point};
+use crate::vec::Vec3;
+
+/// A 4x4 matrix acting on points and vectors in homogeneous coordinates,
+/// stored by rows. Only affine transforms are expected, so the bottom row is
+/// left alone when transforming and points are never divided through by w.
+///
+/// Products compose right to left like the maths: `a * b` applies `b` first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Self = Self {
+        m: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+    };
+
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Matrix4 { m }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut m = Self::IDENTITY.m;
+        m[0][3] = offset.x;
+        m[1][3] = offset.y;
+        m[2][3] = offset.z;
+        Matrix4 { m }
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        let mut m = Self::IDENTITY.m;
+        m[0][0] = factors.x;
+        m[1][1] = factors.y;
+        m[2][2] = factors.z;
+        Matrix4 { m }
+    }
+
+    /// Rotation by `degrees` about `axis` through the origin, counterclockwise
+    /// when looking down the axis toward the origin (Rodrigues' formula).
+    pub fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let k = 1.0 - cos;
+        Matrix4 {
+            m: [
+                [cos + a.x * a.x * k, a.x * a.y * k - a.z * sin, a.x * a.z * k + a.y * sin, 0.0],
+                [a.y * a.x * k + a.z * sin, cos + a.y * a.y * k, a.y * a.z * k - a.x * sin, 0.0],
+                [a.z * a.x * k - a.y * sin, a.z * a.y * k + a.x * sin, cos + a.z * a.z * k, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    /// The inverse by Gauss-Jordan elimination, or `None` if the matrix is
+    /// singular (a zero scale, say).
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::IDENTITY.m;
+        for col in 0..4 {
+            // Partial pivoting: swap up the row with the largest entry.
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1.0e-12 { return None }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col { continue }
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Matrix4 { m: inv })
+    }
+
+    #[inline]
+    pub fn transform_point(&self, p: &Point) -> Point {
+        let m = &self.m;
+        Point::new(m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+                   m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+                   m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3])
+    }
+
+    /// Like `transform_point`, but ignoring the translation.
+    #[inline]
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+                  m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+                  m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z)
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1.0e-9;
+
+    fn near(a: &Matrix4, b: &Matrix4) -> bool {
+        a.m.iter().flatten().zip(b.m.iter().flatten()).all(|(x, y)| (x - y).abs() < EPSILON)
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let matrices = [
+            Matrix4::translation(Vec3::new(1.0, -2.0, 3.0)),
+            Matrix4::scaling(Vec3::new(2.0, 0.5, -4.0)),
+            Matrix4::rotation(Vec3::new(1.0, 1.0, 0.0), 37.0),
+            Matrix4::translation(Vec3::new(0.0, 5.0, 0.0))
+                * Matrix4::rotation(Vec3::Z_HAT, 90.0)
+                * Matrix4::scaling(Vec3::new(1.0, 3.0, 1.0)),
+            // Needs a row swap: the first pivot is zero.
+            Matrix4::new([[0.0, 1.0, 0.0, 0.0], [1.0, 0.0, 0.0, 2.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]),
+        ];
+        for matrix in matrices {
+            let inverse = matrix.inverse().expect("invertible");
+            assert!(near(&(matrix * inverse), &Matrix4::IDENTITY), "{:?}", matrix);
+            assert!(near(&(inverse * matrix), &Matrix4::IDENTITY), "{:?}", matrix);
+        }
+
+        let inverse = Matrix4::translation(Vec3::new(1.0, 2.0, 3.0)).inverse().unwrap();
+        assert!(near(&inverse, &Matrix4::translation(Vec3::new(-1.0, -2.0, -3.0))));
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert!(Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+        // Two equal rows.
+        let m = Matrix4::new([[1.0, 2.0, 3.0, 0.0], [1.0, 2.0, 3.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        assert!(m.inverse().is_none());
+    }
+
+    #[test]
+    fn points_move_and_vectors_only_turn() {
+        let m = Matrix4::translation(Vec3::new(1.0, 0.0, 0.0)) * Matrix4::rotation(Vec3::Z_HAT, 90.0);
+        assert!((m.transform_point(&Point::new(1.0, 0.0, 0.0)) - Point::new(1.0, 1.0, 0.0)).near_zero());
+        assert!((m.transform_vector(&Vec3::X_HAT) - Vec3::Y_HAT).near_zero());
+        assert!(near(&m.transpose().transpose(), &m));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HitList, HitRecord, Hittable};
+use crate::material::{Isotropic, Material, MatKind};
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::render::Color;
+use crate::vec::Vec3;
+
+use rand::Rng;
+
+/// A volume of constant density filling the inside of `boundary`, like smoke
+/// or fog. A ray crossing it scatters at a random distance in, more likely the
+/// denser the medium, or passes straight through. Scattering is up to
+/// `phase_function`, normally an `Isotropic` material.
+///
+/// The boundary has to be closed and convex for the inside to be well defined.
+///
+/// A volume has no surface for `hit` to find: the distance a ray gets through
+/// it is random, and has to be drawn from the path's generator so that every
+/// volume along a ray gets a fresh draw. The integrators go through `Media`
+/// for that, which `Media::extract` builds from the volumes in the world.
+#[derive(Clone, Debug)]
+pub struct ConstantMedium<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub boundary: Box<Hittable<Mat>>,
+    neg_inv_density: f64,
+    pub phase_function: Mat,
+}
+
+impl<Mat> ConstantMedium<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(boundary: Hittable<Mat>, density: f64, phase_function: Mat) -> Self {
+        ConstantMedium { boundary: Box::new(boundary), neg_inv_density: -1.0 / density, phase_function }
+    }
+
+    pub fn density(&self) -> f64 {
+        -1.0 / self.neg_inv_density
+    }
+
+    /// The part of `ray` between `t_min` and `t_max` that is inside the
+    /// boundary, if any. Where the ray enters and leaves is found regardless
+    /// of the range, so rays starting inside the volume work too.
+    pub fn span(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut rec1 = HitRecord::empty();
+        let mut rec2 = HitRecord::empty();
+        if !self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY, &mut rec1) { return None }
+        if !self.boundary.hit(ray, rec1.t + 0.0001, f64::INFINITY, &mut rec2) { return None }
+
+        let t1 = rec1.t.max(t_min).max(0.0);
+        let t2 = rec2.t.min(t_max);
+        if t1 >= t2 { return None }
+        Some((t1, t2))
+    }
+
+    /// Where, if anywhere between `t_min` and `t_max`, `ray` scatters in the
+    /// volume.
+    pub fn sample<R: Rng + ?Sized>(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut R) -> Option<f64> {
+        let (t1, t2) = self.span(ray, t_min, t_max)?;
+        let ray_length = ray.dir.length();
+        let hit_distance = self.neg_inv_density * (1.0 - rng.gen::<f64>()).ln();
+        if hit_distance > (t2 - t1) * ray_length { return None }
+        Some(t1 + hit_distance / ray_length)
+    }
+
+    /// Fraction of light that makes it along `ray` from `t_min` to `t_max`
+    /// without scattering in the volume.
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.span(ray, t_min, t_max) {
+            Some((t1, t2)) => (-self.density() * (t2 - t1) * ray.dir.length()).exp(),
+            None => 1.0,
+        }
+    }
+}
+
+impl<Mat> Hit<Mat> for ConstantMedium<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    /// Never hits; see `Media`.
+    fn hit<'a>(&'a self, _ray: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord<'a, Mat>) -> bool {
+        false
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+/// A `ConstantMedium` somewhere in the world, along with the matrix that takes
+/// world rays into its own space if a transform placed it.
+#[derive(Clone, Debug)]
+struct Volume {
+    medium: ConstantMedium<MatKind>,
+    to_local: Option<Matrix4>,
+}
+
+impl Volume {
+    /// `ray` in the medium's space. The direction isn't renormalized, so `t`
+    /// means the same on both sides.
+    fn to_local(&self, ray: &Ray) -> Ray {
+        match &self.to_local {
+            Some(m) => Ray::with_time(m.transform_point(&ray.origin), m.transform_vector(&ray.dir), ray.time),
+            None => *ray,
+        }
+    }
+}
+
+/// Everything between the surfaces of a scene that light can scatter off: the
+/// fog filling the scene, if any, and the `ConstantMedium` volumes. A ray
+/// draws an independent free-flight distance in each with the generator it
+/// is given, and scatters at the nearest, so light crossing several volumes
+/// gets through with the product of their transmittances.
+#[derive(Clone, Debug, Default)]
+pub struct Media {
+    pub fog: Option<Fog>,
+    volumes: Vec<Volume>,
+}
+
+impl Media {
+    /// Takes the volumes out of `world`, at the top level or placed by
+    /// transforms, and returns the surfaces that are left along with them.
+    pub fn extract(world: HitList<MatKind>, fog: Option<Fog>) -> (HitList<MatKind>, Self) {
+        let mut media = Media { fog, volumes: Vec::new() };
+        let mut surfaces = HitList::new();
+        for hittable in world.into_inner() {
+            match volume(&hittable, &Matrix4::IDENTITY) {
+                Some(volume) => media.volumes.push(volume),
+                None => surfaces.push(hittable),
+            }
+        }
+        (surfaces, media)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fog.is_none() && self.volumes.is_empty()
+    }
+
+    /// Where, if anywhere before `t_max`, `ray` scatters, as a hit record for
+    /// the volume there. `t_max` is where the ray hits a surface, or infinity
+    /// if it escapes; fog only fills the space up to something the ray hits,
+    /// a surface or a volume.
+    pub fn scatter<R: Rng + ?Sized>(&self, ray: &Ray, t_max: f64, rng: &mut R) -> Option<HitRecord<'_, MatKind>> {
+        let mut closest = t_max;
+        let mut phase_function = None;
+        for volume in self.volumes.iter() {
+            if let Some(t) = volume.medium.sample(&volume.to_local(ray), T_MIN, closest, rng) {
+                closest = t;
+                phase_function = Some(&volume.medium.phase_function);
+            }
+        }
+        if closest.is_finite() {
+            if let Some(fog) = &self.fog {
+                let scattered = fog.scatter(ray, closest, rng);
+                if scattered.is_some() { return scattered }
+            }
+        }
+        phase_function.map(|phase_function| HitRecord::new(ray.at(closest), Vec3::X_HAT, closest, phase_function, None))
+    }
+
+    /// Fraction of light that makes it along `ray` to `ray.at(t)` without
+    /// scattering, for shadow rays.
+    pub fn transmittance(&self, ray: &Ray, t: f64) -> f64 {
+        let volumes: f64 = self.volumes.iter().map(|v| v.medium.transmittance(&v.to_local(ray), T_MIN, t)).product();
+        let fog = self.fog.as_ref().map_or(1.0, |fog| fog.transmittance(t * ray.dir.length()));
+        volumes * fog
+    }
+}
+
+/// Same as the integrators use for surfaces, so a ray leaving a point in a
+/// volume doesn't scatter again right where it started.
+const T_MIN: f64 = 0.001;
+
+/// `hittable` as a volume if it is one, placed in the world by `matrix`.
+fn volume(hittable: &Hittable<MatKind>, matrix: &Matrix4) -> Option<Volume> {
+    match hittable {
+        Hittable::ConstantMedium(medium) => {
+            let to_local = if *matrix == Matrix4::IDENTITY { None } else { Some(matrix.inverse()?) };
+            Some(Volume { medium: medium.clone(), to_local })
+        }
+        Hittable::Transform(transform) => volume(&transform.object, &(*matrix * *transform.matrix())),
+        _ => None,
+    }
+}
+
+/// Homogeneous fog filling the space between the surfaces of the scene. Rays
+/// that escape to the background leave it unaffected, so a sky still lights
+/// the scene through fog of any density.
+#[derive(Clone, Debug)]
+pub struct Fog {
+    pub density: f64,
+    /// An `Isotropic` material with the fog's albedo.
+    pub phase_function: MatKind,
+}
+
+impl Fog {
+    pub fn new(density: f64, albedo: Color) -> Self {
+        Fog { density, phase_function: MatKind::Isotropic(Isotropic::new(albedo)) }
+    }
+
+    /// Fraction of light that makes it `distance` through the fog unscattered.
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        (-self.density * distance).exp()
+    }
+
+    /// Where, if anywhere before `t_max`, `ray` scatters off the fog, as a hit
+    /// record for an isotropic volume there.
+    pub fn scatter<R: Rng + ?Sized>(&self, ray: &Ray, t_max: f64, rng: &mut R) -> Option<HitRecord<'_, MatKind>> {
+        let distance = -(1.0 - rng.gen::<f64>()).ln() / self.density;
+        let t = distance / ray.dir.length();
+        if t >= t_max { return None }
+
+        Some(HitRecord::new(ray.at(t), Vec3::X_HAT, t, &self.phase_function, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::quad::BoxShape;
+    use crate::render::Point;
+    use crate::transform::Transform;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Two volumes of density 0.5 along the x axis, one unit deep from x = 0
+    /// and two units deep from x = 3, the second placed by a transform.
+    fn media() -> Media {
+        let smoke = MatKind::Isotropic(Isotropic::new(Color::new(0.8, 0.8, 0.8)));
+        let slab = |depth: f64| {
+            let boundary = BoxShape::new(Point::new(0.0, -1.0, -1.0), Point::new(depth, 1.0, 1.0), smoke.clone());
+            Hittable::ConstantMedium(ConstantMedium::new(Hittable::BoxShape(Box::new(boundary)), 0.5, smoke.clone()))
+        };
+        let moved = Transform::new(Arc::new(slab(2.0)), Matrix4::translation(Vec3::new(3.0, 0.0, 0.0))).unwrap();
+
+        let mut world = HitList::new();
+        world.push(slab(1.0));
+        world.push(Hittable::Transform(Box::new(moved)));
+        let (surfaces, media) = Media::extract(world, None);
+        assert!(surfaces.is_empty());
+        media
+    }
+
+    #[test]
+    fn transmittance_through_several_volumes_multiplies() {
+        let media = media();
+        let ray = Ray::new(Point::new(-1.0, 0.0, 0.0), Vec3::X_HAT);
+        let expected = f64::exp(-0.5 * (1.0 + 2.0));
+        assert!((media.transmittance(&ray, f64::INFINITY) - expected).abs() < 1.0e-12);
+        // Up to a surface halfway through the second volume.
+        assert!((media.transmittance(&ray, 5.0) - f64::exp(-0.5 * 2.0)).abs() < 1.0e-12);
+
+        // Sampling agrees: the fraction of rays that get through unscattered
+        // is the product, not what the deeper volume lets through alone.
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let n = 20_000;
+        let mut through = 0;
+        for _ in 0..n {
+            match media.scatter(&ray, f64::INFINITY, &mut rng) {
+                Some(rec) => {
+                    let x = rec.p.x;
+                    assert!((0.0..=1.0).contains(&x) || (3.0..=5.0).contains(&x), "scattered outside at {}", x);
+                    assert_eq!(rec.front_face, None);
+                }
+                None => through += 1,
+            }
+        }
+        let fraction = through as f64 / n as f64;
+        assert!((fraction - expected).abs() < 0.015, "{} got through, expected {}", fraction, expected);
+    }
+
+    #[test]
+    fn volumes_never_hit_as_surfaces() {
+        let media = media();
+        let medium = &media.volumes[0].medium;
+        let mut rec = HitRecord::empty();
+        assert!(!medium.hit(&Ray::new(Point::new(-1.0, 0.0, 0.0), Vec3::X_HAT), 0.0, f64::INFINITY, &mut rec));
+        // A ray starting inside only crosses the rest of the volume.
+        let inside = Ray::new(Point::new(0.25, 0.0, 0.0), Vec3::X_HAT);
+        let (t1, t2) = medium.span(&inside, 0.0, f64::INFINITY).unwrap();
+        assert!(t1 == 0.0 && (t2 - 0.75).abs() < 1.0e-9);
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::triangle::{interpolate, intersect, set_hit_record, triangle_box};
+use crate::vec::Vec3;
+
+/// Vertex attributes shared by every face of one or more meshes. Positions,
+/// normals and texture coordinates are indexed separately (as in an OBJ file),
+/// so a vertex on a hard edge can reuse its position with different normals.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Point>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+}
+
+/// One triangle of a mesh, as indices into `MeshData`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+impl MeshFace {
+    pub fn new(positions: [usize; 3], normals: Option<[usize; 3]>, uvs: Option<[usize; 3]>) -> Self {
+        MeshFace { positions, normals, uvs }
+    }
+}
+
+/// A triangle mesh with a single material. The vertex buffer lives behind an
+/// `Arc` so that several meshes (one per material, say) can be cut from the same
+/// buffer, and the faces are kept in their own BVH so a mesh sits in the world
+/// as one object no matter how many triangles it has. The faces are only
+/// indices; the buffer and the material are the mesh's, shared by all of them.
+#[derive(Clone)]
+pub struct Mesh<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub data: Arc<MeshData>,
+    pub material: Mat,
+    faces: Bvh<Mat, MeshFace>,
+}
+
+impl<Mat> Mesh<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    /// # Panics
+    ///
+    /// If any face indexes past the end of the corresponding buffer in `data`.
+    pub fn new(data: Arc<MeshData>, faces: Vec<MeshFace>, material: Mat) -> Self {
+        for face in faces.iter() {
+            assert!(face.positions.iter().all(|&i| i < data.positions.len()), "mesh position index out of range");
+            assert!(face.normals.iter().flatten().all(|&i| i < data.normals.len()), "mesh normal index out of range");
+            assert!(face.uvs.iter().flatten().all(|&i| i < data.uvs.len()), "mesh uv index out of range");
+        }
+
+        let faces = Bvh::with_boxes(faces, |face| {
+            let [v0, v1, v2] = face.positions.map(|i| data.positions[i]);
+            Some(triangle_box(v0, v1, v2))
+        });
+        Mesh { data, material, faces }
+    }
+
+    #[inline]
+    fn vertices(&self, face: &MeshFace) -> [Point; 3] {
+        face.positions.map(|i| self.data.positions[i])
+    }
+
+    #[inline]
+    fn uvs(&self, face: &MeshFace) -> Option<[(f64, f64); 3]> {
+        face.uvs.map(|uvs| uvs.map(|i| self.data.uvs[i]))
+    }
+
+    fn hit_face<'a>(&'a self,
+                    face: &MeshFace,
+                    ray: &Ray,
+                    t_min: f64,
+                    t_max: f64,
+                    hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let [v0, v1, v2] = self.vertices(face);
+        let (t, b1, b2) = match intersect(ray, v0, v1, v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let normals = face.normals.map(|[i0, i1, i2]| {
+            interpolate(self.data.normals[i0], self.data.normals[i1], self.data.normals[i2], b1, b2)
+        });
+        set_hit_record(ray, t, v0, v1, v2, normals, self.uvs(face), (b1, b2), &self.material, hit_record);
+        true
+    }
+
+    /// The corners of every face.
+    pub fn triangles(&self) -> impl Iterator<Item = [Point; 3]> + '_ {
+        self.faces.iter().map(|face| self.vertices(face))
+    }
+
+    #[allow(unused)]
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    #[allow(unused)]
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+}
+
+impl<Mat> Hit<Mat> for Mesh<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        self.faces.traverse(ray, t_min, t_max, hit_record, |face, closest_so_far, rec| {
+            self.hit_face(face, ray, t_min, closest_so_far, rec)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.faces.bounds()
+    }
+}
+
+/// The derived version would print every vertex once per face.
+impl<Mat> fmt::Debug for Mesh<Mat>
+where
+    Mat: Material + Clone + Default + fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mesh")
+            .field("vertices", &self.data.positions.len())
+            .field("faces", &self.faces.len())
+            .field("material", &self.material)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, MatKind};
+    use crate::render::Color;
+    use crate::triangle::Triangle;
+
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn faces_hit_like_separate_triangles_with_the_mesh_material() {
+        // A grid of quads, two faces each, with per-vertex uvs.
+        let mut data = MeshData::default();
+        let n = 8;
+        for j in 0..=n {
+            for i in 0..=n {
+                let (x, y) = (i as f64 / n as f64, j as f64 / n as f64);
+                data.positions.push(Point::new(x, y, 0.1 * (x * 7.0).sin() * y));
+                data.uvs.push((x, y));
+            }
+        }
+        let at = |i: usize, j: usize| j * (n + 1) + i;
+        let mut faces = Vec::new();
+        for j in 0..n {
+            for i in 0..n {
+                let (a, b, c, d) = (at(i, j), at(i + 1, j), at(i + 1, j + 1), at(i, j + 1));
+                for corners in [[a, b, c], [a, c, d]] {
+                    faces.push(MeshFace::new(corners, None, Some(corners)));
+                }
+            }
+        }
+        let material = MatKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let triangles: Vec<Triangle<MatKind>> = faces
+            .iter()
+            .map(|face| Triangle::new(face.positions.map(|i| data.positions[i]), None,
+                                      face.uvs.map(|uvs| uvs.map(|i| data.uvs[i])), material.clone()))
+            .collect();
+        let mesh = Mesh::new(Arc::new(data), faces, material);
+        assert_eq!(mesh.len(), 2 * n * n);
+
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Point::new(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0), 2.0);
+            let ray = Ray::new(origin, Vec3::new(rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1), -1.0));
+            let mut rec = HitRecord::empty();
+            let mut expected = HitRecord::empty();
+            let hit = mesh.hit(&ray, 0.001, f64::INFINITY, &mut rec);
+            let mut closest = f64::INFINITY;
+            for triangle in triangles.iter() {
+                if triangle.hit(&ray, 0.001, closest, &mut expected) { closest = expected.t; }
+            }
+            assert_eq!(hit, closest.is_finite());
+            if !hit { continue }
+            hits += 1;
+            assert_eq!((rec.t, rec.p, rec.normal), (expected.t, expected.p, expected.normal));
+            assert_eq!((rec.u, rec.v), (expected.u, expected.v));
+            // Every face borrows the one material the mesh holds.
+            assert!(std::ptr::eq(rec.material(), &mesh.material));
+        }
+        assert!(hits > 1000);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::render::{Color, PI};
+use crate::vec::Vec3;
+
+use rand::Rng;
+
+/// The GGX (Trowbridge-Reitz) microfacet distribution with Smith shadowing.
+/// Angles are passed in as cosines against the shading normal, so callers can
+/// stay in world space and only `sample_half` needs a local frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    /// Roughness is squared into alpha so that it reads roughly linearly, as in
+    /// most tools. Alpha is kept off zero, where the distribution degenerates
+    /// into a mirror that can't be evaluated.
+    pub fn from_roughness(roughness: f64) -> Self {
+        Ggx { alpha: (roughness * roughness).max(1.0e-4) }
+    }
+
+    /// Density of microfacet normals `cos_h` away from the macro normal.
+    pub fn d(&self, cos_h: f64) -> f64 {
+        if cos_h <= 0.0 { return 0.0 }
+        let a2 = self.alpha * self.alpha;
+        let denom = cos_h * cos_h * (a2 - 1.0) + 1.0;
+        a2 / (PI * denom * denom)
+    }
+
+    /// Smith masking for a direction `cos_v` away from the normal.
+    pub fn g1(&self, cos_v: f64) -> f64 {
+        if cos_v <= 0.0 { return 0.0 }
+        let a2 = self.alpha * self.alpha;
+        2.0 * cos_v / (cos_v + (a2 + (1.0 - a2) * cos_v * cos_v).sqrt())
+    }
+
+    /// Shadowing and masking together, treating the two as independent.
+    pub fn g(&self, cos_o: f64, cos_i: f64) -> f64 {
+        self.g1(cos_o) * self.g1(cos_i)
+    }
+
+    /// A microfacet normal about +z, distributed as `d(cos_h) * cos_h`.
+    pub fn sample_half<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec3 {
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let tan2 = self.alpha * self.alpha * r1 / (1.0 - r1);
+        let cos_theta = 1.0 / (1.0 + tan2).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * r2;
+        Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+    }
+
+    /// BRDF, without the cosine, of a GGX reflector whose Fresnel reflectance at
+    /// normal incidence is `f0`. Directions are unit vectors pointing away from
+    /// the surface, both on the side of `n`.
+    pub fn eval_reflection(&self, f0: Color, wo: &Vec3, wi: &Vec3, n: &Vec3) -> Color {
+        let (cos_o, cos_i) = (wo.dot(n), wi.dot(n));
+        if cos_o <= 0.0 || cos_i <= 0.0 { return Color::default() }
+        let h = (*wo + *wi).unit_vector();
+        schlick(f0, wo.dot(&h)) * (self.d(h.dot(n)) * self.g(cos_o, cos_i) / (4.0 * cos_o * cos_i))
+    }
+
+    /// Density with which `sample_reflection` picks `wi`.
+    pub fn pdf_reflection(&self, wo: &Vec3, wi: &Vec3, n: &Vec3) -> f64 {
+        if wo.dot(n) <= 0.0 || wi.dot(n) <= 0.0 { return 0.0 }
+        let h = (*wo + *wi).unit_vector();
+        let wo_dot_h = wo.dot(&h);
+        if wo_dot_h <= 0.0 { return 0.0 }
+        self.d(h.dot(n)) * h.dot(n) / (4.0 * wo_dot_h)
+    }
+
+    /// Reflects `wo` about a sampled microfacet normal, or `None` if that
+    /// sends it below the surface.
+    pub fn sample_reflection<R: Rng + ?Sized>(&self, wo: &Vec3, n: &Vec3, rng: &mut R) -> Option<Vec3> {
+        let h = to_world(&self.sample_half(rng), n);
+        let wi = (-*wo).reflect(h);
+        if wo.dot(&h) <= 0.0 || wi.dot(n) <= 0.0 { return None }
+        Some(wi)
+    }
+}
+
+/// Schlick's approximation to Fresnel reflectance, for a color at normal
+/// incidence `f0`.
+#[inline]
+pub fn schlick(f0: Color, cos: f64) -> Color {
+    let k = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * k
+}
+
+/// Rotates `local`, given in a frame with +z along `normal`, into world space.
+#[inline]
+pub fn to_world(local: &Vec3, normal: &Vec3) -> Vec3 {
+    let (w, u, v) = normal.coordinate_system_from();
+    u * local.x + v * local.y + w * local.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const ROUGHNESSES: [f64; 4] = [0.1, 0.3, 0.6, 1.0];
+
+    /// Integral of `f(θ)` over the directions between `from` and `to` radians
+    /// away from +z, by the midpoint rule.
+    fn integrate(f: impl Fn(f64) -> f64, from: f64, to: f64) -> f64 {
+        let steps = 100_000;
+        let dtheta = (to - from) / steps as f64;
+        (0..steps)
+            .map(|i| {
+                let theta = from + (i as f64 + 0.5) * dtheta;
+                f(theta) * theta.sin() * dtheta * 2.0 * PI
+            })
+            .sum()
+    }
+
+    #[test]
+    fn projected_microfacet_area_is_one() {
+        for roughness in ROUGHNESSES {
+            let ggx = Ggx::from_roughness(roughness);
+            let area = integrate(|theta| ggx.d(theta.cos()) * theta.cos(), 0.0, PI / 2.0);
+            assert!((area - 1.0).abs() < 1.0e-3, "roughness {}: {}", roughness, area);
+        }
+    }
+
+    #[test]
+    fn sampled_half_vectors_follow_the_pdf() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let (bins, samples) = (16, 200_000);
+        for roughness in ROUGHNESSES {
+            let ggx = Ggx::from_roughness(roughness);
+            let mut histogram = vec![0; bins];
+            for _ in 0..samples {
+                let h = ggx.sample_half(&mut rng);
+                assert!((h.length() - 1.0).abs() < 1.0e-9 && h.z >= 0.0);
+                let bin = (h.z.clamp(0.0, 1.0).acos() / (PI / 2.0) * bins as f64) as usize;
+                histogram[bin.min(bins - 1)] += 1;
+            }
+            for (bin, &count) in histogram.iter().enumerate() {
+                let width = PI / 2.0 / bins as f64;
+                let from = bin as f64 * width;
+                let expected = integrate(|theta| ggx.d(theta.cos()) * theta.cos(), from, from + width);
+                let observed = count as f64 / samples as f64;
+                assert!(
+                    (observed - expected).abs() < 0.005,
+                    "roughness {}, bin {}: {} sampled, {} expected", roughness, bin, observed, expected,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::sphere::hit_sphere;
+use crate::vec::Vec3;
+
+/// A sphere moving in a straight line from `center0` at `time0` to `center1`
+/// at `time1`, which blurs along its path when the camera's shutter is open
+/// for a while. Outside that interval it stays put at the nearer end.
+#[derive(Clone, Debug)]
+pub struct MovingSphere<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub center0: Point,
+    pub center1: Point,
+    pub time0: f64,
+    pub time1: f64,
+    pub r: f64,
+    pub material: Mat,
+}
+
+impl<Mat> MovingSphere<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(center0: Point, center1: Point, time0: f64, time1: f64, r: f64, material: Mat) -> Self {
+        MovingSphere { center0, center1, time0, time1, r, material }
+    }
+
+    pub fn center(&self, time: f64) -> Point {
+        if self.time1 <= self.time0 { return self.center0 }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + (self.center1 - self.center0) * s
+    }
+}
+
+impl<Mat> Hit<Mat> for MovingSphere<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        hit_sphere(self.center(ray.time), self.r, &self.material, ray, t_min, t_max, hit_record)
+    }
+
+    /// Everywhere the sphere goes, so a BVH finds it whatever the ray's time.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.r.abs(), self.r.abs(), self.r.abs());
+        let box0 = Aabb::new(self.center0 - r, self.center0 + r);
+        let box1 = Aabb::new(self.center1 - r, self.center1 + r);
+        Some(box0.surrounding(&box1))
+    }
+}
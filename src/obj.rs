@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+use crate::material::{Bumped, Dielectric, DiffuseLight, Lambertian, MatKind, Metal, NormalMap, Principled};
+use crate::mesh::{Mesh, MeshData, MeshFace};
+use crate::render::Color;
+use crate::texture::{ImageTexture, Texture};
+use crate::vec::Vec3;
+
+/// Loads a Wavefront OBJ file into one `Mesh` per material, all sharing a single
+/// vertex buffer. Materials come from the `.mtl` libraries the file references
+/// (see `load_mtl` for how they map onto `MatKind`); faces before any `usemtl`,
+/// or every face if `material` is given, use `material` or a grey `Lambertian`.
+///
+/// Polygons are fan triangulated, negative (relative) indices are resolved and
+/// faces without normals are shaded flat. Statements the renderer has no use
+/// for, such as groups, smoothing groups and lines, are skipped.
+pub fn load_obj(path: &Path, material: Option<MatKind>) -> Result<Vec<Mesh<MatKind>>, ObjError> {
+    let text = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let default_material = material.clone().unwrap_or_else(|| MatKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+
+    let mut data = MeshData::default();
+    let mut library: HashMap<String, MatKind> = HashMap::new();
+    // Faces grouped by material, in order of first use so output is stable.
+    let mut groups: Vec<(MatKind, Vec<MeshFace>)> = vec![(default_material, Vec::new())];
+    let mut group_of: HashMap<String, usize> = HashMap::new();
+    let mut current = 0;
+
+    for (i, line) in text.lines().enumerate() {
+        let err = |message: String| ObjError::Parse { path: path.to_path_buf(), line: i + 1, message };
+        let line = line.split('#').next().unwrap();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => data.positions.push(parse_vec3(&mut tokens).map_err(err)?),
+            "vn" => data.normals.push(parse_vec3(&mut tokens).map_err(err)?),
+            "vt" => {
+                let u = parse_f64(tokens.next(), "u").map_err(err)?;
+                let v = tokens.next().map_or(Ok(0.0), |t| parse_f64(Some(t), "v")).map_err(err)?;
+                data.uvs.push((u, v));
+            }
+            "f" => {
+                let corners = tokens
+                    .map(|t| parse_corner(t, &data))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(err)?;
+                if corners.len() < 3 {
+                    return Err(err(format!("face has {} vertices, expected at least 3", corners.len())))
+                }
+                let faces = &mut groups[current].1;
+                // A corner missing its normal or uv means the whole polygon goes without.
+                let has_normals = corners.iter().all(|c| c.1.is_some());
+                let has_uvs = corners.iter().all(|c| c.2.is_some());
+                for k in 1..corners.len() - 1 {
+                    let [a, b, c] = [corners[0], corners[k], corners[k + 1]];
+                    faces.push(MeshFace::new(
+                        [a.0, b.0, c.0],
+                        if has_normals { Some([a.1.unwrap(), b.1.unwrap(), c.1.unwrap()]) } else { None },
+                        if has_uvs { Some([a.2.unwrap(), b.2.unwrap(), c.2.unwrap()]) } else { None },
+                    ));
+                }
+            }
+            "mtllib" => {
+                if material.is_some() { continue }
+                // File names may contain spaces, so take the rest of the line.
+                let name = line.trim_start()[keyword.len()..].trim();
+                if name.is_empty() { return Err(err("mtllib without a file name".to_string())) }
+                library.extend(load_mtl(&dir.join(name))?);
+            }
+            "usemtl" => {
+                if material.is_some() { continue }
+                let name = line.trim_start()[keyword.len()..].trim();
+                current = match group_of.get(name) {
+                    Some(&group) => group,
+                    None => {
+                        let mat = library.get(name).ok_or_else(|| err(format!("unknown material `{}`", name)))?.clone();
+                        groups.push((mat, Vec::new()));
+                        group_of.insert(name.to_string(), groups.len() - 1);
+                        groups.len() - 1
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+
+    let data = Arc::new(data);
+    Ok(groups
+        .into_iter()
+        .filter(|(_, faces)| !faces.is_empty())
+        .map(|(mat, faces)| Mesh::new(Arc::clone(&data), faces, mat))
+        .collect())
+}
+
+/// Reads the materials of an MTL library. The MTL illumination model is far
+/// richer than ours, so each entry is mapped onto the closest `MatKind`:
+///
+/// - entries using the PBR extension (`Pr`, `Pm`, `Pc` or `Pcr`) become a
+///   `Principled` built from those plus `Kd`/`map_Kd`, `Ke`, `Ni` and `d`,
+/// - emissive (`Ke` other than black) becomes a `DiffuseLight`,
+/// - transparent (`d` < 1, `Tr` > 0, or `illum` 4, 6, 7 or 9) becomes a
+///   `Dielectric` with index of refraction `Ni` (1.5 if missing), tinted by
+///   the transmission filter `Tf`,
+/// - mirror-like (`illum` 3, 5 or 8) becomes a `Metal` with albedo `Ks`,
+///   rough or sharp depending on the specular exponent `Ns`,
+/// - anything else becomes a `Lambertian` with albedo `Kd`, or the image
+///   texture `map_Kd` if there is one.
+///
+/// A tangent space normal map under `norm`, or failing that a bump map under
+/// `bump` or `map_Bump` (scaled by its `-bm` option), wraps the result in a
+/// `Bumped` material.
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, MatKind>, ObjError> {
+    let text = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let err = |message: String| ObjError::Parse { path: path.to_path_buf(), line: i + 1, message };
+        let line = line.split('#').next().unwrap();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            if let Some((name, entry)) = current.take() {
+                materials.insert(name, entry.into_material());
+            }
+            let name = line.trim_start()[keyword.len()..].trim();
+            if name.is_empty() { return Err(err("newmtl without a name".to_string())) }
+            current = Some((name.to_string(), MtlEntry::default()));
+            continue;
+        }
+
+        let entry = match current.as_mut() {
+            Some((_, entry)) => entry,
+            None => {
+                // Some exporters write comments-as-statements up top; only complain
+                // about statements that actually describe a material.
+                const STATEMENTS: [&str; 17] = [
+                    "Kd", "Ks", "Ke", "Ns", "Ni", "d", "Tr", "Tf", "illum", "map_Kd", "Pr", "Pm", "Pc", "Pcr",
+                    "norm", "bump", "map_Bump",
+                ];
+                if STATEMENTS.contains(&keyword) {
+                    return Err(err(format!("`{}` before any newmtl", keyword)))
+                }
+                continue;
+            }
+        };
+
+        match keyword {
+            "Kd" => entry.kd = parse_vec3(&mut tokens).map_err(err)?,
+            "Ks" => entry.ks = parse_vec3(&mut tokens).map_err(err)?,
+            "Ke" => entry.ke = parse_vec3(&mut tokens).map_err(err)?,
+            "Tf" => entry.tf = parse_vec3(&mut tokens).map_err(err)?,
+            "Ns" => entry.ns = Some(parse_f64(tokens.next(), "Ns").map_err(err)?),
+            "Pr" | "Pm" | "Pc" | "Pcr" => {
+                let value = Some(parse_f64(tokens.next(), keyword).map_err(err)?.clamp(0.0, 1.0));
+                match keyword {
+                    "Pr" => entry.roughness = value,
+                    "Pm" => entry.metallic = value,
+                    "Pc" => entry.clearcoat = value,
+                    _ => entry.clearcoat_roughness = value,
+                }
+                entry.pbr = true;
+            }
+            "Ni" => {
+                let ni = parse_f64(tokens.next(), "Ni").map_err(err)?;
+                if ni <= 0.0 { return Err(err(format!("Ni: index of refraction must be positive, got {}", ni))) }
+                entry.ni = Some(ni);
+            }
+            "d" => entry.dissolve = parse_f64(tokens.next(), "d").map_err(err)?,
+            "Tr" => entry.dissolve = 1.0 - parse_f64(tokens.next(), "Tr").map_err(err)?,
+            "illum" => {
+                let illum = tokens.next().ok_or_else(|| err("illum without a value".to_string()))?;
+                entry.illum = illum.parse().map_err(|_| err(format!("expected an integer, found `{}`", illum)))?;
+            }
+            "map_Kd" => {
+                // Options such as `-s` or `-bm` come first; the file name is last.
+                let name = tokens.last().ok_or_else(|| err("map_Kd without a file name".to_string()))?;
+                let image = ImageTexture::load(&dir.join(name)).map_err(|e| err(e.to_string()))?;
+                entry.map_kd = Some(Arc::new(image));
+            }
+            "norm" => {
+                let name = tokens.last().ok_or_else(|| err("norm without a file name".to_string()))?;
+                let image = ImageTexture::load_raw(&dir.join(name)).map_err(|e| err(e.to_string()))?;
+                entry.norm = Some(Arc::new(image));
+            }
+            "bump" | "map_Bump" => {
+                let tokens: Vec<&str> = tokens.collect();
+                let name = tokens.last().ok_or_else(|| err(format!("{} without a file name", keyword)))?;
+                if let Some(i) = tokens.iter().position(|&option| option == "-bm") {
+                    entry.bump_scale = parse_f64(tokens.get(i + 1).copied(), "-bm").map_err(err)?;
+                }
+                let image = ImageTexture::load_raw(&dir.join(name)).map_err(|e| err(e.to_string()))?;
+                entry.bump = Some(Arc::new(image));
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, entry)) = current.take() {
+        materials.insert(name, entry.into_material());
+    }
+
+    Ok(materials)
+}
+
+#[derive(Clone, Debug)]
+struct MtlEntry {
+    kd: Color,
+    map_kd: Option<Arc<ImageTexture>>,
+    /// Normal map, which wins over the bump map if there are both.
+    norm: Option<Arc<ImageTexture>>,
+    bump: Option<Arc<ImageTexture>>,
+    bump_scale: f64,
+    ks: Color,
+    ke: Color,
+    /// Transmission filter, the color of light that makes it through.
+    tf: Color,
+    ns: Option<f64>,
+    ni: Option<f64>,
+    /// Whether any of the PBR extension's keywords turned up.
+    pbr: bool,
+    roughness: Option<f64>,
+    metallic: Option<f64>,
+    clearcoat: Option<f64>,
+    clearcoat_roughness: Option<f64>,
+    dissolve: f64,
+    illum: u32,
+}
+
+impl Default for MtlEntry {
+    fn default() -> Self {
+        MtlEntry {
+            kd: Color::new(0.8, 0.8, 0.8),
+            map_kd: None,
+            norm: None,
+            bump: None,
+            bump_scale: 1.0,
+            ks: Color::default(),
+            ke: Color::default(),
+            tf: Color::new(1.0, 1.0, 1.0),
+            ns: None,
+            ni: None,
+            pbr: false,
+            roughness: None,
+            metallic: None,
+            clearcoat: None,
+            clearcoat_roughness: None,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlEntry {
+    fn into_material(self) -> MatKind {
+        let map = match (self.norm.clone(), self.bump.clone()) {
+            (Some(norm), _) => Some(NormalMap::Normal(Texture::Image(norm))),
+            (None, Some(bump)) => Some(NormalMap::Height { height: Texture::Image(bump), scale: self.bump_scale }),
+            (None, None) => None,
+        };
+        let material = self.surface();
+        match map {
+            // Lights are left alone so they can still be sampled as lights.
+            Some(map) if !matches!(material, MatKind::DiffuseLight(_)) => {
+                MatKind::Bumped(Box::new(Bumped::new(material, map)))
+            }
+            _ => material,
+        }
+    }
+
+    fn surface(self) -> MatKind {
+        let base = self.map_kd.clone().map_or(Texture::Solid(self.kd), Texture::Image);
+        if self.pbr {
+            let defaults = Principled::default();
+            MatKind::Principled(Principled {
+                base_color: base,
+                metallic: self.metallic.unwrap_or(defaults.metallic),
+                roughness: self.roughness.unwrap_or(defaults.roughness),
+                transmission: 1.0 - self.dissolve,
+                ior: self.ni.unwrap_or(defaults.ior),
+                clearcoat: self.clearcoat.unwrap_or(defaults.clearcoat),
+                clearcoat_roughness: self.clearcoat_roughness.unwrap_or(defaults.clearcoat_roughness),
+                emission: self.ke,
+                ..defaults
+            })
+        } else if !self.ke.near_zero() {
+            MatKind::DiffuseLight(DiffuseLight::new(self.ke))
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            MatKind::Dielectric(Dielectric::tinted(self.ni.unwrap_or(1.5), self.tf, Color::default()))
+        } else if matches!(self.illum, 3 | 5 | 8) {
+            // The usual Blinn-Phong exponent to microfacet width conversion.
+            let roughness = self.ns.map_or(0.0, |ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt().sqrt());
+            MatKind::Metal(Metal::new(self.ks, roughness))
+        } else {
+            MatKind::Lambertian(Lambertian::new(base))
+        }
+    }
+}
+
+fn parse_f64(token: Option<&str>, what: &str) -> Result<f64, String> {
+    let token = token.ok_or_else(|| format!("missing {}", what))?;
+    token.parse().map_err(|_| format!("expected a number for {}, found `{}`", what, token))
+}
+
+fn parse_vec3(tokens: &mut SplitWhitespace) -> Result<Vec3, String> {
+    let x = parse_f64(tokens.next(), "x")?;
+    let y = parse_f64(tokens.next(), "y")?;
+    let z = parse_f64(tokens.next(), "z")?;
+    Ok(Vec3::new(x, y, z))
+}
+
+/// Parses one `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner into zero based
+/// (position, normal, uv) indices.
+fn parse_corner(token: &str, data: &MeshData) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = token.split('/');
+    let position = resolve_index(parts.next(), data.positions.len(), "vertex", token)?
+        .ok_or_else(|| format!("face vertex `{}` has no position index", token))?;
+    let uv = resolve_index(parts.next(), data.uvs.len(), "texture coordinate", token)?;
+    let normal = resolve_index(parts.next(), data.normals.len(), "normal", token)?;
+    if parts.next().is_some() { return Err(format!("malformed face vertex `{}`", token)) }
+    Ok((position, normal, uv))
+}
+
+/// OBJ indices start at 1, and negative indices count back from the most
+/// recently defined element.
+fn resolve_index(part: Option<&str>, len: usize, what: &str, token: &str) -> Result<Option<usize>, String> {
+    let part = match part {
+        Some(part) if !part.is_empty() => part,
+        _ => return Ok(None),
+    };
+    let index: i64 = part.parse().map_err(|_| format!("malformed face vertex `{}`", token))?;
+    let resolved = match index {
+        0 => return Err(format!("{} index 0 in `{}` is invalid, indices start at 1", what, token)),
+        i if i > 0 => i - 1,
+        i => len as i64 + i,
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("{} index {} in `{}` is out of range, only {} defined so far", what, index, token, len))
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            Self::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::{Hit, HitRecord};
+    use crate::ray::Ray;
+
+    /// Writes `files` into a fresh directory of their own and loads the first as
+    /// an OBJ file.
+    fn load(test: &str, files: &[(&str, &str)]) -> Result<Vec<Mesh<MatKind>>, ObjError> {
+        let dir = std::env::temp_dir().join(format!("ray-trace-obj-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        let result = load_obj(&dir.join(files[0].0), None);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    fn parse_error(result: Result<Vec<Mesh<MatKind>>, ObjError>) -> (usize, String) {
+        match result {
+            Err(ObjError::Parse { line, message, .. }) => (line, message),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    fn data(positions: usize, uvs: usize, normals: usize) -> MeshData {
+        MeshData {
+            positions: vec![Vec3::default(); positions],
+            normals: vec![Vec3::default(); normals],
+            uvs: vec![(0.0, 0.0); uvs],
+        }
+    }
+
+    #[test]
+    fn corners_with_and_without_uvs_and_normals() {
+        let data = data(4, 3, 2);
+        assert_eq!(parse_corner("2", &data), Ok((1, None, None)));
+        assert_eq!(parse_corner("2/3", &data), Ok((1, None, Some(2))));
+        assert_eq!(parse_corner("2//1", &data), Ok((1, Some(0), None)));
+        assert_eq!(parse_corner("4/1/2", &data), Ok((3, Some(1), Some(0))));
+        assert!(parse_corner("1/1/1/1", &data).is_err());
+        assert!(parse_corner("a/1", &data).is_err());
+        assert!(parse_corner("/1", &data).is_err());
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element() {
+        let data = data(4, 3, 2);
+        assert_eq!(parse_corner("-1/-1/-1", &data), Ok((3, Some(1), Some(2))));
+        assert_eq!(parse_corner("-4//-2", &data), Ok((0, Some(0), None)));
+        assert!(parse_corner("-5", &data).unwrap_err().contains("out of range"));
+        assert!(parse_corner("0", &data).unwrap_err().contains("indices start at 1"));
+        assert!(parse_corner("1/4", &data).unwrap_err().contains("texture coordinate index 4"));
+    }
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v -0.5 0.5 0
+f 1 2 3
+f 1 2 3 4
+f -5 -4 -3 -2 -1
+";
+        let meshes = load("fan", &[("fan.obj", obj)]).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].len(), 1 + 2 + 3);
+        assert_eq!(meshes[0].data.positions.len(), 5);
+    }
+
+    #[test]
+    fn relative_indices_refer_to_vertices_defined_so_far() {
+        // Each face only sees the vertices above it, so the same relative
+        // indices pick out different triangles.
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 0 1
+vn 0 0 1
+f -3/-3/-1 -2/-2/-1 -1/-1/-1
+v 0 0 1
+v 1 0 1
+v 0 1 1
+f -3/-3/-1 -2/-2/-1 -1/-1/-1
+";
+        let meshes = load("relative", &[("relative.obj", obj)]).unwrap();
+        assert_eq!(meshes[0].len(), 2);
+
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let mut rec = HitRecord::empty();
+        let ray = Ray::new(Vec3::new(0.2, 0.2, 2.0), down);
+        assert!(meshes[0].hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.t - 1.0).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn bad_indices_report_their_line() {
+        let obj = "\
+# a comment
+v 0 0 0
+v 1 0 0
+v 0 1 0
+
+f 1 2 4
+";
+        let (line, message) = parse_error(load("bad-index", &[("bad.obj", obj)]));
+        assert_eq!(line, 6);
+        assert_eq!(message, "vertex index 4 in `4` is out of range, only 3 defined so far");
+
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//0\n";
+        let (line, message) = parse_error(load("zero-index", &[("zero.obj", obj)]));
+        assert_eq!(line, 5);
+        assert!(message.contains("normal index 0"), "{}", message);
+
+        let obj = "v 0 0 0\nv 1 0 0\nf 1 2\n";
+        let (line, message) = parse_error(load("short-face", &[("short.obj", obj)]));
+        assert_eq!(line, 3);
+        assert_eq!(message, "face has 2 vertices, expected at least 3");
+    }
+
+    #[test]
+    fn usemtl_groups_faces_by_material() {
+        let mtl = "newmtl red\nKd 0.8 0.1 0.1\n\nnewmtl lamp\nKe 4 4 4\n";
+        let obj = "\
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+usemtl red
+f 1 2 3
+f 1 3 2
+usemtl lamp
+f 1 2 3
+usemtl red
+f 3 2 1
+";
+        let meshes = load("usemtl", &[("scene.obj", obj), ("scene.mtl", mtl)]).unwrap();
+        let sizes: Vec<usize> = meshes.iter().map(|mesh| mesh.len()).collect();
+        assert_eq!(sizes, [1, 3, 1]);
+        assert!(matches!(meshes[0].material, MatKind::Lambertian(_)));
+        assert!(matches!(meshes[1].material, MatKind::Lambertian(_)));
+        assert!(matches!(meshes[2].material, MatKind::DiffuseLight(_)));
+    }
+
+    #[test]
+    fn usemtl_with_an_unknown_material_is_an_error() {
+        let mtl = "newmtl red\nKd 0.8 0.1 0.1\n";
+        let obj = "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl blue\nf 1 2 3\n";
+        let (line, message) = parse_error(load("unknown-mtl", &[("scene.obj", obj), ("scene.mtl", mtl)]));
+        assert_eq!(line, 5);
+        assert_eq!(message, "unknown material `blue`");
+
+        // Without any library at all, too.
+        let obj = "v 0 0 0\nusemtl red\n";
+        let (line, _) = parse_error(load("no-library", &[("scene.obj", obj)]));
+        assert_eq!(line, 2);
+    }
+
+    #[test]
+    fn mtl_index_of_refraction_must_be_positive() {
+        let obj = "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl glass\nf 1 2 3\n";
+        for (ni, got) in [("0", "0"), ("-1.5", "-1.5")] {
+            let mtl = format!("newmtl glass\nillum 7\nNi {}\n", ni);
+            match load("bad-ni", &[("scene.obj", obj), ("scene.mtl", &mtl)]) {
+                Err(ObjError::Parse { path, line, message }) => {
+                    assert!(path.ends_with("scene.mtl"), "{}", path.display());
+                    assert_eq!(line, 3);
+                    assert_eq!(message, format!("Ni: index of refraction must be positive, got {}", got));
+                }
+                Err(e) => panic!("expected a parse error, got {}", e),
+                Ok(_) => panic!("expected a parse error"),
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+use crate::render::{write_color_to_pixel_buffer, Color};
+
+/// File formats the renderer can write, chosen from the output file extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// 8-bit sRGB-ish (gamma 2) PNG.
+    Png,
+    /// Binary (P6) PPM with the same 8-bit encoding as the PNG.
+    Ppm,
+    /// Portable float map: the linear radiance exactly as rendered, unclamped.
+    Pfm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase()).as_deref() {
+            Some("png") => Ok(Self::Png),
+            Some("ppm") => Ok(Self::Ppm),
+            Some("pfm") => Ok(Self::Pfm),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("can't tell the image format of {}: use a .png, .ppm or .pfm extension", path.display()),
+            )),
+        }
+    }
+}
+
+/// Writes a `width` by `height` image of linear radiance, stored top row first,
+/// in the format given by the extension of `path`.
+pub fn write_image(path: &Path, width: usize, height: usize, pixels: &[Color]) -> Result<()> {
+    assert_eq!(pixels.len(), width * height);
+    let format = ImageFormat::from_path(path)?;
+    let mut file = BufWriter::new(File::create(path)?);
+
+    match format {
+        ImageFormat::Png => write_png(&mut file, width, height, pixels)?,
+        ImageFormat::Ppm => write_ppm(&mut file, width, height, pixels)?,
+        ImageFormat::Pfm => write_pfm(&mut file, width, height, pixels)?,
+    }
+    file.flush()
+}
+
+pub(crate) fn quantize(pixels: &[Color]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(pixels.len() * 3);
+    for &color in pixels {
+        let (r, g, b) = write_color_to_pixel_buffer(color);
+        bytes.extend_from_slice(&[r, g, b]);
+    }
+    bytes
+}
+
+fn write_png<W: Write>(out: W, width: usize, height: usize, pixels: &[Color]) -> Result<()> {
+    let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&quantize(pixels)).map_err(png_error)?;
+    writer.finish().map_err(png_error)
+}
+
+fn png_error(e: png::EncodingError) -> Error {
+    match e {
+        png::EncodingError::IoError(e) => e,
+        e => Error::other(e),
+    }
+}
+
+fn write_ppm<W: Write>(mut out: W, width: usize, height: usize, pixels: &[Color]) -> Result<()> {
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    out.write_all(&quantize(pixels))
+}
+
+/// PFM stores rows bottom to top; the negative scale marks little-endian data.
+fn write_pfm<W: Write>(mut out: W, width: usize, height: usize, pixels: &[Color]) -> Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in pixels.chunks(width).rev() {
+        for color in row {
+            for channel in [color.x, color.y, color.z] {
+                out.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 image, top row first: black and white on top, then a quarter
+    /// grey and something far too bright to fit in 8 bits.
+    fn pixels() -> [Color; 4] {
+        [Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0), Color::new(0.25, 0.25, 0.25), Color::new(4.0, 0.0, 9.0)]
+    }
+
+    /// The same, gamma corrected and clamped.
+    const BYTES: [u8; 12] = [0, 0, 0, 255, 255, 255, 128, 128, 128, 255, 0, 255];
+
+    #[test]
+    fn ppm_is_a_p6_header_and_bytes() {
+        let mut out = Vec::new();
+        write_ppm(&mut out, 2, 2, &pixels()).unwrap();
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(out[header.len()..], BYTES);
+    }
+
+    #[test]
+    fn png_decodes_to_the_same_bytes() {
+        let mut out = Vec::new();
+        write_png(&mut out, 2, 2, &pixels()).unwrap();
+        let mut reader = png::Decoder::new(out.as_slice()).read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+        assert_eq!((info.width, info.height, info.color_type), (2, 2, png::ColorType::Rgb));
+        assert_eq!(decoded[..info.buffer_size()], BYTES);
+    }
+
+    #[test]
+    fn pfm_is_little_endian_bottom_row_first_and_unclamped() {
+        let mut out = Vec::new();
+        write_pfm(&mut out, 2, 2, &pixels()).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+        let floats: Vec<f32> = out[header.len()..]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let [top_left, top_right, bottom_left, bottom_right] = pixels();
+        let expected: Vec<f32> = [bottom_left, bottom_right, top_left, top_right]
+            .iter()
+            .flat_map(|c| [c.x as f32, c.y as f32, c.z as f32])
+            .collect();
+        assert_eq!(floats, expected);
+    }
+
+    #[test]
+    fn formats_come_from_the_extension() {
+        assert_eq!(ImageFormat::from_path(Path::new("out.png")).unwrap(), ImageFormat::Png);
+        assert_eq!(ImageFormat::from_path(Path::new("dir/OUT.PPM")).unwrap(), ImageFormat::Ppm);
+        assert_eq!(ImageFormat::from_path(Path::new("out.pfm")).unwrap(), ImageFormat::Pfm);
+        for bad in ["out.jpg", "out"] {
+            assert_eq!(ImageFormat::from_path(Path::new(bad)).unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::aabb::Aabb;
+use crate::frame::Frame;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::{Point, PI};
+use crate::triangle::BOX_PADDING;
+use crate::vec::Vec3;
+
+/// The infinite plane through `point` facing along `normal`. It has no
+/// bounding box, so it is tested against every ray rather than sitting in a
+/// BVH. Texture coordinates repeat every unit along two directions in the plane.
+#[derive(Clone, Debug)]
+pub struct Plane<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    frame: Frame,
+    pub material: Mat,
+}
+
+impl<Mat> Plane<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(point: Point, normal: Vec3, material: Mat) -> Self {
+        Plane { frame: Frame::new(point, normal), material }
+    }
+}
+
+impl<Mat> Hit<Mat> for Plane<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let o = self.frame.to_local(&ray.origin);
+        let d = self.frame.vector_to_local(&ray.dir);
+        let t = -o.z / d.z;
+        // Also catches rays parallel to the plane, where t is infinite or NaN.
+        if !(t_min..=t_max).contains(&t) { return false }
+
+        hit_record.t = t;
+        hit_record.p = ray.at(t);
+        hit_record.set_face_normal(ray, &self.frame.w);
+        hit_record.set_tangents(&self.frame.u, &self.frame.v);
+        hit_record.u = (o.x + t * d.x).rem_euclid(1.0);
+        hit_record.v = (o.y + t * d.y).rem_euclid(1.0);
+        hit_record.material = Some(&self.material);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// A flat disk of `radius` about `center`, facing along `normal`. `u` goes
+/// around the center and `v` out from it.
+#[derive(Clone, Debug)]
+pub struct Disk<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    frame: Frame,
+    pub radius: f64,
+    pub material: Mat,
+}
+
+impl<Mat> Disk<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(center: Point, normal: Vec3, radius: f64, material: Mat) -> Self {
+        Disk { frame: Frame::new(center, normal), radius, material }
+    }
+}
+
+impl<Mat> Hit<Mat> for Disk<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let o = self.frame.to_local(&ray.origin);
+        let d = self.frame.vector_to_local(&ray.dir);
+        let t = -o.z / d.z;
+        if !(t_min..=t_max).contains(&t) { return false }
+        let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+        let r = (x * x + y * y).sqrt();
+        if r > self.radius { return false }
+
+        hit_record.t = t;
+        hit_record.p = ray.at(t);
+        hit_record.set_face_normal(ray, &self.frame.w);
+        let (dpdu, dpdv) = disk_derivatives(x, y, self.radius);
+        hit_record.set_tangents(&self.frame.vector_to_world(&dpdu), &self.frame.vector_to_world(&dpdv));
+        (hit_record.u, hit_record.v) = (polar_angle(x, y), r / self.radius);
+        hit_record.material = Some(&self.material);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(disk_box(self.frame.origin, &self.frame.w, self.radius))
+    }
+}
+
+/// Angle of (x, y) about the origin as a fraction of a full turn, from 0 to 1.
+#[inline]
+pub(crate) fn polar_angle(x: f64, y: f64) -> f64 {
+    (y.atan2(x) + PI) / (2.0 * PI)
+}
+
+/// How a point `(x, y)` on a disk of `radius` about the origin moves with its
+/// texture coordinates: `polar_angle` around and the distance out over
+/// `radius`. Going out has no direction at the center itself.
+#[inline]
+pub(crate) fn disk_derivatives(x: f64, y: f64, radius: f64) -> (Vec3, Vec3) {
+    let dpdu = Vec3::new(-y, x, 0.0) * (2.0 * PI);
+    let r = (x * x + y * y).sqrt();
+    if r == 0.0 { return (dpdu, Vec3::default()) }
+    (dpdu, Vec3::new(x, y, 0.0) * (radius / r))
+}
+
+/// Box around a circle of `radius` about `center` in the plane with unit
+/// normal `n`: along each world axis the circle reaches `radius` times the sine
+/// of the angle between that axis and `n`.
+pub(crate) fn disk_box(center: Point, n: &Vec3, radius: f64) -> Aabb {
+    let reach = |c: f64| radius * (1.0 - c * c).max(0.0).sqrt() + BOX_PADDING;
+    let e = Vec3::new(reach(n.x), reach(n.y), reach(n.z));
+    Aabb::new(center - e, center + e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::MatKind;
+
+    fn hits<H: Hit<MatKind>>(shape: &H, ray: &Ray) -> bool {
+        shape.hit(ray, 0.001, f64::INFINITY, &mut HitRecord::empty())
+    }
+
+    #[test]
+    fn parallel_rays_miss() {
+        let normal = Vec3::new(0.0, 1.0, 1.0);
+        let plane = Plane::new(Point::ORIGIN, normal, MatKind::default());
+        let disk = Disk::new(Point::ORIGIN, normal, 1.0, MatKind::default());
+        let along = Vec3::new(0.0, 1.0, -1.0);
+        // In the plane itself, where t comes out 0 / 0, and just off it.
+        for origin in [Point::new(0.0, -0.5, 0.5), Point::new(0.0, -0.5, 0.6)] {
+            for dir in [along, -along] {
+                let ray = Ray::new(origin, dir);
+                assert!(!hits(&plane, &ray) && !hits(&disk, &ray), "{:?}", ray);
+            }
+        }
+        // A slight tilt is enough to hit.
+        let ray = Ray::new(Point::new(0.0, -0.5, 0.6), Vec3::new(0.0, 1.0, -1.1));
+        assert!(hits(&plane, &ray) && hits(&disk, &ray));
+    }
+
+    #[test]
+    fn disks_end_at_their_radius() {
+        let disk = Disk::new(Point::new(0.0, 0.0, 1.0), Vec3::Z_HAT, 1.0, MatKind::default());
+        let mut rec = HitRecord::empty();
+        assert!(disk.hit(&Ray::new(Point::new(0.5, 0.0, 3.0), -Vec3::Z_HAT), 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.t - 2.0).abs() < 1.0e-9 && (rec.v - 0.5).abs() < 1.0e-9);
+        assert!(!hits(&disk, &Ray::new(Point::new(1.1, 0.0, 3.0), -Vec3::Z_HAT)));
+    }
+}
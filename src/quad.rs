@@ -0,0 +1,165 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::triangle::BOX_PADDING;
+use crate::vec::Vec3;
+
+use rand::Rng;
+
+/// A parallelogram with one corner at `q` and sides `u` and `v`. The front is
+/// the side `u x v` points to, and (u, v) at a hit run from 0 to 1 along the
+/// two sides.
+#[derive(Clone, Debug)]
+pub struct Quad<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub q: Point,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Mat,
+    normal: Vec3,
+    /// The plane is `normal . p = d`.
+    d: f64,
+    /// `n / n.n` for the unnormalized normal n, which turns a point in the
+    /// plane into its coordinates along `u` and `v`.
+    w: Vec3,
+}
+
+impl<Mat> Quad<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(q: Point, u: Vec3, v: Vec3, material: Mat) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        Quad { q, u, v, material, normal, d: normal.dot(&q), w: n / n.dot(&n) }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.u.cross(&self.v).length()
+    }
+
+    /// Ray parameter and (u, v) of where `ray` crosses the quad, if it does.
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = self.normal.dot(&ray.dir);
+        // Parallel to the plane.
+        if denom.abs() < 1.0e-8 { return None }
+        let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+        if t < t_min || t_max < t { return None }
+
+        let planar = ray.at(t) - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) { return None }
+        Some((t, alpha, beta))
+    }
+
+    /// Direction from `origin` to a point picked uniformly over the quad's
+    /// area, for sampling quad lights.
+    pub fn sample_direction<R: Rng + ?Sized>(&self, origin: Point, rng: &mut R) -> Vec3 {
+        let p = self.q + self.u * rng.gen::<f64>() + self.v * rng.gen::<f64>();
+        p - origin
+    }
+
+    /// Solid angle density of `sample_direction` for `dir`: the area density
+    /// converted by the squared distance over the cosine at the light.
+    pub fn pdf_direction(&self, origin: Point, dir: &Vec3) -> f64 {
+        let (t, _, _) = match self.intersect(&Ray::new(origin, *dir), 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let distance_squared = t * t * dir.length_squared();
+        let cosine = (dir.dot(&self.normal) / dir.length()).abs();
+        if cosine <= 0.0 { return 0.0 }
+        distance_squared / (cosine * self.area())
+    }
+}
+
+impl<Mat> Hit<Mat> for Quad<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let (t, alpha, beta) = match self.intersect(ray, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        hit_record.t = t;
+        hit_record.p = ray.at(t);
+        hit_record.set_face_normal(ray, &self.normal);
+        hit_record.set_tangents(&self.u, &self.v);
+        (hit_record.u, hit_record.v) = (alpha, beta);
+        hit_record.material = Some(&self.material);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let pad = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        let corners = [self.q, self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let bbox = corners.iter().fold(Aabb::EMPTY, |acc, p| acc.grow(p));
+        Some(Aabb::new(bbox.min - pad, bbox.max + pad))
+    }
+}
+
+/// An axis-aligned box between two opposite corners, made of six quads facing
+/// outward.
+#[derive(Clone, Debug)]
+pub struct BoxShape<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub min: Point,
+    pub max: Point,
+    sides: [Quad<Mat>; 6],
+}
+
+impl<Mat> BoxShape<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(a: Point, b: Point, material: Mat) -> Self {
+        let (min, max) = (a.min(&b), a.max(&b));
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+        let side = |q: Point, u: Vec3, v: Vec3| Quad::new(q, u, v, material.clone());
+        let sides = [
+            side(Point::new(min.x, min.y, max.z), dx, dy),  // front
+            side(Point::new(max.x, min.y, max.z), -dz, dy), // right
+            side(Point::new(max.x, min.y, min.z), -dx, dy), // back
+            side(Point::new(min.x, min.y, min.z), dz, dy),  // left
+            side(Point::new(min.x, max.y, max.z), dx, -dz), // top
+            side(Point::new(min.x, min.y, min.z), dx, dz),  // bottom
+        ];
+        BoxShape { min, max, sides }
+    }
+
+    pub fn sides(&self) -> &[Quad<Mat>; 6] {
+        &self.sides
+    }
+}
+
+impl<Mat> Hit<Mat> for BoxShape<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a, Mat>) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        for side in self.sides.iter() {
+            if side.hit(ray, t_min, closest_so_far, rec) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let pad = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        Some(Aabb::new(self.min - pad, self.max + pad))
+    }
+}
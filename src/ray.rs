@@ -4,11 +4,18 @@ use crate::render::Point;
 pub struct Ray {
     pub origin: Point,
     pub dir: Point,
+    /// When the ray was sent, somewhere in the camera's shutter interval. Rays
+    /// scattered off a surface carry the time of the ray that hit it.
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Point, dir: Point) -> Self {
-        Self { origin, dir }
+        Self::with_time(origin, dir, 0.0)
+    }
+
+    pub fn with_time(origin: Point, dir: Point, time: f64) -> Self {
+        Self { origin, dir, time }
     }
     
     #[inline]
@@ -0,0 +1,224 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::quad::Quad;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::triangle::BOX_PADDING;
+use crate::vec::Vec3;
+
+/// The rectangle `[x0, x1] x [y0, y1]` in the plane `z = k`, facing +z.
+#[derive(Clone, Debug)]
+pub struct XyRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub x0: f64,
+    pub x1: f64,
+    pub y0: f64,
+    pub y1: f64,
+    pub k: f64,
+    pub material: Mat,
+}
+
+/// The rectangle `[x0, x1] x [z0, z1]` in the plane `y = k`, facing +y.
+#[derive(Clone, Debug)]
+pub struct XzRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub x0: f64,
+    pub x1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+    pub material: Mat,
+}
+
+/// The rectangle `[y0, y1] x [z0, z1]` in the plane `x = k`, facing +x.
+#[derive(Clone, Debug)]
+pub struct YzRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub y0: f64,
+    pub y1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+    pub material: Mat,
+}
+
+impl<Mat> XyRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Mat) -> Self {
+        XyRect { x0, x1, y0, y1, k, material }
+    }
+
+    /// The same rectangle as a general quad, facing the same way and with the
+    /// same (u, v) at every point.
+    pub fn to_quad(&self) -> Quad<Mat> {
+        let q = Point::new(self.x0, self.y0, self.k);
+        let (u, v) = (Vec3::new(self.x1 - self.x0, 0.0, 0.0), Vec3::new(0.0, self.y1 - self.y0, 0.0));
+        Quad::new(q, u, v, self.material.clone())
+    }
+}
+
+impl<Mat> XzRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Mat) -> Self {
+        XzRect { x0, x1, z0, z1, k, material }
+    }
+
+    /// The same rectangle as a general quad, with the same (u, v) at every
+    /// point. Here u runs along x and v along z, and x cross z is -y, so
+    /// unlike the other rectangles' quads this one faces the other way.
+    pub fn to_quad(&self) -> Quad<Mat> {
+        let q = Point::new(self.x0, self.k, self.z0);
+        let (u, v) = (Vec3::new(self.x1 - self.x0, 0.0, 0.0), Vec3::new(0.0, 0.0, self.z1 - self.z0));
+        Quad::new(q, u, v, self.material.clone())
+    }
+}
+
+impl<Mat> YzRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Mat) -> Self {
+        YzRect { y0, y1, z0, z1, k, material }
+    }
+
+    pub fn to_quad(&self) -> Quad<Mat> {
+        let q = Point::new(self.k, self.y0, self.z0);
+        let (u, v) = (Vec3::new(0.0, self.y1 - self.y0, 0.0), Vec3::new(0.0, 0.0, self.z1 - self.z0));
+        Quad::new(q, u, v, self.material.clone())
+    }
+}
+
+/// The three rectangles only differ in which axes they span. `a` and `b` are
+/// those axes and `c` the one the rectangle faces along.
+#[allow(clippy::too_many_arguments)]
+#[inline]
+fn hit_rect<'a, Mat>(
+    ray: &Ray,
+    (a, b, c): (usize, usize, usize),
+    (a0, a1, b0, b1): (f64, f64, f64, f64),
+    k: f64,
+    material: &'a Mat,
+    t_min: f64,
+    t_max: f64,
+    hit_record: &mut HitRecord<'a, Mat>) -> bool
+where
+    Mat: Material + Clone + Default
+{
+    let t = (k - ray.origin.component(c)) / ray.dir.component(c);
+    // Also catches rays parallel to the plane, where t is infinite or NaN.
+    if !(t_min..=t_max).contains(&t) { return false }
+
+    let p = ray.at(t);
+    let (pa, pb) = (p.component(a), p.component(b));
+    if pa < a0 || pa > a1 || pb < b0 || pb > b1 { return false }
+
+    let axis = |i: usize| match i {
+        0 => Vec3::X_HAT,
+        1 => Vec3::Y_HAT,
+        _ => Vec3::Z_HAT,
+    };
+    let outward_normal = axis(c);
+    hit_record.t = t;
+    hit_record.p = p;
+    hit_record.set_face_normal(ray, &outward_normal);
+    hit_record.set_tangents(&(axis(a) * (a1 - a0)), &(axis(b) * (b1 - b0)));
+    (hit_record.u, hit_record.v) = ((pa - a0) / (a1 - a0), (pb - b0) / (b1 - b0));
+    hit_record.material = Some(material);
+    true
+}
+
+/// Boxes for rectangles are padded out of the plane so they aren't flat.
+fn rect_box(min: Point, max: Point) -> Aabb {
+    let pad = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+    Aabb::new(min - pad, max + pad)
+}
+
+impl<Mat> Hit<Mat> for XyRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let bounds = (self.x0, self.x1, self.y0, self.y1);
+        hit_rect(ray, (0, 1, 2), bounds, self.k, &self.material, t_min, t_max, hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(rect_box(Point::new(self.x0, self.y0, self.k), Point::new(self.x1, self.y1, self.k)))
+    }
+}
+
+impl<Mat> Hit<Mat> for XzRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let bounds = (self.x0, self.x1, self.z0, self.z1);
+        hit_rect(ray, (0, 2, 1), bounds, self.k, &self.material, t_min, t_max, hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(rect_box(Point::new(self.x0, self.k, self.z0), Point::new(self.x1, self.k, self.z1)))
+    }
+}
+
+impl<Mat> Hit<Mat> for YzRect<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let bounds = (self.y0, self.y1, self.z0, self.z1);
+        hit_rect(ray, (1, 2, 0), bounds, self.k, &self.material, t_min, t_max, hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(rect_box(Point::new(self.k, self.y0, self.z0), Point::new(self.k, self.y1, self.z1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::MatKind;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Checks that rays from all around hit `rect` and its quad at the same
+    /// place and (u, v), and that the quad faces the way `facing` says.
+    fn matches_quad<H: Hit<MatKind>>(rect: &H, quad: &Quad<MatKind>, center: Point, facing: f64) {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..100 {
+            let origin = center + Vec3::random_unit_vector(&mut rng) * 10.0;
+            let target = center + Vec3::random(-0.4, 0.4, &mut rng);
+            let ray = Ray::new(origin, target - origin);
+            let (mut a, mut b) = (HitRecord::empty(), HitRecord::empty());
+            let hit = rect.hit(&ray, 0.0, f64::INFINITY, &mut a);
+            assert_eq!(hit, quad.hit(&ray, 0.0, f64::INFINITY, &mut b));
+            if !hit { continue }
+            assert!((a.t - b.t).abs() < 1.0e-9 && (a.p - b.p).near_zero());
+            assert!((a.u - b.u).abs() < 1.0e-9 && (a.v - b.v).abs() < 1.0e-9, "{:?} vs {:?}", (a.u, a.v), (b.u, b.v));
+            assert!((a.outward_normal() * facing - b.outward_normal()).near_zero());
+        }
+    }
+
+    #[test]
+    fn rectangles_and_their_quads_agree() {
+        let rect = XyRect::new(-1.0, 1.0, -0.5, 0.5, 0.25, MatKind::default());
+        matches_quad(&rect, &rect.to_quad(), Point::new(0.0, 0.0, 0.25), 1.0);
+        let rect = XzRect::new(-1.0, 1.0, -0.5, 0.5, 0.25, MatKind::default());
+        matches_quad(&rect, &rect.to_quad(), Point::new(0.0, 0.25, 0.0), -1.0);
+        let rect = YzRect::new(-1.0, 1.0, -0.5, 0.5, 0.25, MatKind::default());
+        matches_quad(&rect, &rect.to_quad(), Point::new(0.25, 0.0, 0.0), 1.0);
+    }
+}
@@ -1,14 +1,17 @@
-use std::fs::File;
-use std::io::{Result, Write};
+use std::str::FromStr;
 
-use crate::hit::{Hit, HitList, HitRecord};
+use crate::background::Background;
+use crate::hit::{Hit, HitRecord};
+use crate::light::Lights;
 use crate::material::{Material, MatKind};
+use crate::medium::Media;
 use crate::vec::Vec3;
 use crate::ray::Ray;
 
 use rand::prelude::*;
+use rand_pcg::Pcg64Mcg;
 
-pub const PI: f64 = 3.141592653589793285;
+pub const PI: f64 = std::f64::consts::PI;
 pub const DEG_TO_RAD: f64 = PI / 180.0;
 
 #[inline]
@@ -16,8 +19,31 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees *  DEG_TO_RAD
 }
 
+/// The random number generator used throughout rendering. Unlike `ThreadRng` it
+/// is seedable and cheap to create, so every sample can get its own generator
+/// (see `sample_rng`) and a render doesn't depend on how rayon schedules its
+/// threads or how the samples are split into passes.
+pub type RenderRng = Pcg64Mcg;
+
+/// Generator for sample number `sample` of pixel number `pixel` in a render
+/// seeded with `seed`. Everything is hashed together so neighbouring pixels,
+/// samples and seeds get unrelated streams.
+#[inline]
+pub fn sample_rng(seed: u64, pixel: u64, sample: u64) -> RenderRng {
+    RenderRng::seed_from_u64(splitmix64(splitmix64(seed ^ splitmix64(pixel)) ^ sample))
+}
+
+/// The SplitMix64 finalizer, a cheap and well mixed 64 bit hash.
+#[inline]
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 #[inline]
-pub fn random_f64(min: f64, max: f64, rng: &mut ThreadRng) -> f64 {
+pub fn random_f64<R: Rng + ?Sized>(min: f64, max: f64, rng: &mut R) -> f64 {
     rng.gen_range(min..max)
 }
 
@@ -37,28 +63,33 @@ impl Point {
 
 pub type Color = Vec3;
 
-pub fn write_color_to_pixel_buffer(color: Color, samples_per_pixel: usize) -> (u8, u8, u8) {
-    let mut color = color;
-    let scale = 1.0 / samples_per_pixel as f64;
-    color.x = (scale * color.x).sqrt();
-    color.y = (scale * color.y).sqrt();
-    color.z = (scale * color.z).sqrt();
+/// Converts linear radiance (the mean over a pixel's samples) to 8-bit channels,
+/// gamma correcting with gamma 2 and clamping anything too bright.
+pub fn write_color_to_pixel_buffer(color: Color) -> (u8, u8, u8) {
+    let r = color.x.max(0.0).sqrt();
+    let g = color.y.max(0.0).sqrt();
+    let b = color.z.max(0.0).sqrt();
 
-    let ir = (256.0 * clamp(color.x, 0.0, 0.999)) as u8;
-    let ig = (256.0 * clamp(color.y, 0.0, 0.999)) as u8;
-    let ib = (256.0 * clamp(color.z, 0.0, 0.999)) as u8;
+    let ir = (256.0 * clamp(r, 0.0, 0.999)) as u8;
+    let ig = (256.0 * clamp(g, 0.0, 0.999)) as u8;
+    let ib = (256.0 * clamp(b, 0.0, 0.999)) as u8;
 
     (ir, ig, ib)
 }
 
-pub fn write_buffer(file: &mut File, pixels: &[u8]) -> Result<()> {
-    for pix in pixels.chunks(3).rev() {
-        file.write_all(format!("{} {} {}\n", pix[0], pix[1], pix[2]).as_bytes())?;
-    }
-    Ok(())
-}
-
-pub fn ray_color(ray: &Ray, world: &HitList<MatKind>, depth: isize, rng: &mut ThreadRng) -> Color {
+/// Follows `ray` around the world, picking up light from emitters along the way
+/// and from the background once it escapes. Rays may also scatter off `media`
+/// (fog and volumes) on their way from one surface to the next.
+pub fn ray_color<W, R>(ray: &Ray,
+                       world: &W,
+                       background: &Background,
+                       media: &Media,
+                       depth: isize,
+                       rng: &mut R) -> Color
+where
+    W: Hit<MatKind>,
+    R: Rng + ?Sized,
+{
     let mut rec = HitRecord::empty();
 
     if depth <= 0 { 
@@ -66,17 +97,198 @@ pub fn ray_color(ray: &Ray, world: &HitList<MatKind>, depth: isize, rng: &mut Th
         return Color::new(0.0, 0.0, 0.0) 
     }
 
-    if world.hit(ray, 0.001, f64::INFINITY, &mut rec) {
-        let mat = rec.material;
-        let scatter = mat.scatter(&ray, &mut rec, rng);
-        if scatter.is_scattered {
-            return ray_color(&scatter.scattered, world, depth - 1, rng) * scatter.attenuation
+    let hit = world.hit(ray, 0.001, f64::INFINITY, &mut rec);
+    match media.scatter(ray, if hit { rec.t } else { f64::INFINITY }, rng) {
+        Some(scattered) => rec = scattered,
+        None if !hit => return background.color(ray),
+        None => {}
+    }
+    rec.normal = rec.material().shading_normal(&rec);
+    let mat = rec.material();
+    // Whatever comes back from this hit is dimmed by the medium the ray
+    // crossed to get here, if it was travelling inside an absorbing object.
+    let absorbed = mat.transmittance(&rec, rec.t * ray.dir.length());
+    let emitted = mat.emitted(&rec);
+    let scatter = mat.scatter(ray, &rec, rng);
+    if scatter.is_scattered {
+        let indirect = ray_color(&scatter.scattered, world, background, media, depth - 1, rng);
+        return (emitted + indirect * scatter.attenuation) * absorbed
+    }
+    emitted * absorbed
+}
+
+/// How radiance is estimated along camera rays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Integrator {
+    /// The book's random walk: light is only found when a bounce happens to
+    /// hit it. Simple, but small lights come out very noisy.
+    #[default]
+    Path,
+    /// Samples a light directly at every diffuse bounce as well as following
+    /// the scattered ray, weighting the two with the power heuristic.
+    Mis,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(Self::Path),
+            "mis" => Ok(Self::Mis),
+            _ => Err(format!("unknown integrator `{}`: expected path or mis", s)),
         }
-        return Color::default()
     }
+}
+
+/// Veach's power heuristic (with beta = 2) for weighting a sample taken with
+/// density `pdf` against another strategy that has density `other` for it.
+#[inline]
+pub fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a + b == 0.0 { return 0.0 }
+    a / (a + b)
+}
+
+/// Like `ray_color`, but with next event estimation: at every diffuse hit a
+/// direction toward one of `lights` is sampled too, and light reached either
+/// way is weighted by multiple importance sampling so neither strategy counts
+/// it twice.
+///
+/// `bsdf_pdf` is the density the previous bounce picked `ray` with, or `None`
+/// for camera rays and specular bounces, whose emission isn't sampled any
+/// other way and so counts in full.
+#[allow(clippy::too_many_arguments)]
+pub fn ray_color_mis<W, R>(ray: &Ray,
+                           world: &W,
+                           lights: &Lights,
+                           background: &Background,
+                           media: &Media,
+                           depth: isize,
+                           bsdf_pdf: Option<f64>,
+                           rng: &mut R) -> Color
+where
+    W: Hit<MatKind>,
+    R: Rng + ?Sized,
+{
+    let mut rec = HitRecord::empty();
+
+    if depth <= 0 { return Color::new(0.0, 0.0, 0.0) }
 
-    let unit_dir = ray.dir.unit_vector();
-    let t = (unit_dir.y + 1.0) * 0.5;
-    
-    Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+    let hit = world.hit(ray, 0.001, f64::INFINITY, &mut rec);
+    match media.scatter(ray, if hit { rec.t } else { f64::INFINITY }, rng) {
+        Some(scattered) => rec = scattered,
+        None if !hit => return background.color(ray),
+        None => {}
+    }
+    rec.normal = rec.material().shading_normal(&rec);
+
+    let mat = rec.material();
+    let absorbed = mat.transmittance(&rec, rec.t * ray.dir.length());
+    let emitted = mat.emitted(&rec);
+    let mut color = match bsdf_pdf {
+        Some(pdf) => emitted * power_heuristic(pdf, lights.pdf(ray.origin, &ray.dir)),
+        None => emitted,
+    };
+
+    let wo = -ray.dir.unit_vector();
+    let sample = match mat.sample(&wo, &rec, rng) {
+        Some(sample) => sample,
+        None => return color * absorbed,
+    };
+    let scattered = Ray::with_time(rec.p, sample.wi, ray.time);
+    if sample.is_specular {
+        let indirect = ray_color_mis(&scattered, world, lights, background, media, depth - 1, None, rng);
+        return (color + indirect * sample.weight) * absorbed
+    }
+
+    // Light sampling. Whatever the shadow ray hits first is what gets seen in
+    // that direction, so occluders need no special case.
+    if let Some(dir) = lights.sample(rec.p, rng) {
+        let wi = dir.unit_vector();
+        let light_pdf = lights.pdf(rec.p, &wi);
+        let surface_pdf = mat.pdf(&wo, &wi, &rec);
+        let mut light_rec = HitRecord::empty();
+        let shadow_ray = Ray::with_time(rec.p, wi, ray.time);
+        if light_pdf > 0.0 && surface_pdf > 0.0 && world.hit(&shadow_ray, 0.001, f64::INFINITY, &mut light_rec) {
+            let mut light = light_rec.material().emitted(&light_rec);
+            light *= media.transmittance(&shadow_ray, light_rec.t);
+            // Volumes scatter by a phase function, which has no cosine term.
+            let cos = if rec.front_face.is_some() { wi.dot(&rec.normal).abs() } else { 1.0 };
+            let f = mat.eval(&wo, &wi, &rec) * cos;
+            color += light * f * (power_heuristic(light_pdf, surface_pdf) / light_pdf);
+        }
+    }
+
+    let indirect = ray_color_mis(&scattered, world, lights, background, media, depth - 1, Some(sample.pdf), rng);
+    (color + indirect * sample.weight) * absorbed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::{HitList, Hittable};
+    use crate::material::{Dielectric, DiffuseLight, Principled};
+    use crate::sphere::Sphere;
+
+    use rand::rngs::StdRng;
+
+    /// Radiance along `ray` in a world of just a unit sphere at the origin made
+    /// of `material`, in front of a uniform `background`, by both integrators.
+    fn ray_colors(ray: &Ray, material: MatKind, background: Color) -> [Color; 2] {
+        let mut world = HitList::new();
+        world.push(Hittable::Sphere(Sphere::new(Point::ORIGIN, 1.0, material)));
+        let lights = Lights::new(&world);
+        let background = Background::Solid(background);
+        let media = Media::default();
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        [
+            ray_color(ray, &world, &background, &media, 10, &mut rng),
+            ray_color_mis(ray, &world, &lights, &background, &media, 10, None, &mut rng),
+        ]
+    }
+
+    /// Radiance along a camera ray straight at the sphere, in a black world.
+    fn camera_ray_color(material: MatKind) -> [Color; 2] {
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        ray_colors(&ray, material, Color::new(0.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn emitters_are_seen_at_their_radiance() {
+        let emit = Color::new(4.0, 2.0, 0.5);
+        for color in camera_ray_color(MatKind::DiffuseLight(DiffuseLight::new(emit))) {
+            assert_eq!(color, emit);
+        }
+        // Nothing else in the world gives off light, so a principled surface
+        // shows just its own emission, or nothing without any.
+        let glowing = Principled { emission: emit, ..Principled::default() };
+        for color in camera_ray_color(MatKind::Principled(glowing)) {
+            assert_eq!(color, emit);
+        }
+        for color in camera_ray_color(MatKind::Principled(Principled::default())) {
+            assert_eq!(color, Color::new(0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn absorbing_glass_dims_light_by_the_distance_crossed_inside() {
+        // An index of one lets rays through the sphere unbent and unreflected,
+        // so the only thing between the ray and the white background is glass:
+        // the chord 2 √(1 - 0.6²) = 1.6 long at this offset for a ray from
+        // outside, whose 4 units to the front face take nothing away, or half
+        // of it for one starting on the sphere's equator. The directions are
+        // deliberately not unit length: it is the distance travelled inside
+        // that counts, not `t`.
+        let absorption = Color::new(0.1, 0.5, 2.0);
+        let glass = MatKind::Dielectric(Dielectric::tinted(1.0, Color::new(1.0, 1.0, 1.0), absorption));
+        let direction = Vec3::new(0.0, 0.0, -2.5);
+        for (origin, distance) in [(Point::new(0.6, 0.0, 5.0), 1.6), (Point::new(0.6, 0.0, 0.0), 0.8)] {
+            let a = absorption * distance;
+            let expected = Color::new((-a.x).exp(), (-a.y).exp(), (-a.z).exp());
+            for color in ray_colors(&Ray::new(origin, direction), glass.clone(), Color::new(1.0, 1.0, 1.0)) {
+                assert!((color - expected).length() < 1.0e-9, "{:?} != {:?}", color, expected);
+            }
+        }
+    }
 }
@@ -0,0 +1,218 @@
+use std::io::Result;
+use std::path::Path;
+
+use crate::accum::Accumulation;
+use crate::background::Background;
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::light::Lights;
+use crate::material::MatKind;
+use crate::medium::Media;
+use crate::output::{quantize, write_image};
+use crate::render::{ray_color, ray_color_mis, sample_rng, Color, Integrator};
+use crate::scene::Scene;
+
+use rand::prelude::*;
+use rayon::prelude::*;
+
+/// A rendered image: linear radiance per pixel, top row first, left to right.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image { width, height, pixels: vec![Color::default(); width * height] }
+    }
+
+    #[inline]
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Gamma corrected, clamped 8-bit RGB, three bytes per pixel.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        quantize(&self.pixels)
+    }
+
+    /// Writes the image in the format given by the extension of `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_image(path, self.width, self.height, &self.pixels)
+    }
+}
+
+/// How to render: image size, samples per pixel, maximum bounces, the seed
+/// every sample is derived from and the integrator.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub depth: isize,
+    pub seed: u64,
+    pub integrator: Integrator,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 400,
+            height: 225,
+            samples: 100,
+            depth: 32,
+            seed: 0,
+            integrator: Integrator::default(),
+        }
+    }
+}
+
+/// A scene made ready for rendering: the world is put into a BVH and the camera
+/// is set up for the image's aspect ratio. Build one and render it as many
+/// times, or in as many passes, as you like.
+///
+/// ```no_run
+/// use std::path::Path;
+/// use ray_trace::renderer::{RenderSettings, Renderer};
+/// use ray_trace::scene::Scene;
+///
+/// let scene = Scene::load(Path::new("scenes/cornell.toml")).unwrap();
+/// let settings = RenderSettings { width: 200, height: 200, ..RenderSettings::default() };
+/// let image = Renderer::new(scene, settings).render();
+/// image.save(Path::new("cornell.png")).unwrap();
+/// ```
+pub struct Renderer {
+    pub settings: RenderSettings,
+    camera: Camera,
+    world: Bvh<MatKind>,
+    lights: Lights,
+    background: Background,
+    media: Media,
+}
+
+impl Renderer {
+    pub fn new(scene: Scene, settings: RenderSettings) -> Self {
+        let aspect_ratio = settings.width as f64 / settings.height as f64;
+        let (world, media) = Media::extract(scene.world, scene.fog);
+        Renderer {
+            settings,
+            camera: scene.camera.build(aspect_ratio),
+            lights: Lights::new(&world),
+            world: Bvh::from(world),
+            background: scene.background,
+            media,
+        }
+    }
+
+    /// The emitters the MIS integrator samples directly.
+    pub fn lights(&self) -> &Lights {
+        &self.lights
+    }
+
+    /// Renders the whole image with `settings.samples` samples per pixel.
+    pub fn render(&self) -> Image {
+        let mut accum = self.accumulation();
+        self.render_pass(&mut accum, self.settings.samples);
+        accum.image()
+    }
+
+    /// An empty accumulation buffer matching these settings, for `render_pass`.
+    pub fn accumulation(&self) -> Accumulation {
+        Accumulation::new(self.settings.width, self.settings.height, self.settings.seed)
+    }
+
+    /// Adds `samples` more samples to every pixel of `accum`.
+    pub fn render_pass(&self, accum: &mut Accumulation, samples: usize) {
+        self.render_pass_with(accum, samples, || {})
+    }
+
+    /// Like `render_pass`, calling `on_row` (from any thread) as each row finishes
+    /// so that callers can show progress.
+    ///
+    /// # Panics
+    ///
+    /// If `accum` isn't the same size as the image.
+    pub fn render_pass_with<F>(&self, accum: &mut Accumulation, samples: usize, on_row: F)
+    where
+        F: Fn() + Sync,
+    {
+        let RenderSettings { width, height, depth, integrator, .. } = self.settings;
+        assert_eq!((accum.width, accum.height), (width, height), "accumulation buffer doesn't match the image size");
+        let seed = accum.seed;
+        let first_sample = accum.samples;
+
+        accum.sums.par_chunks_mut(width).enumerate().for_each(|(row, band)| {
+            let j = height - 1 - row;
+            for (i, pixel) in band.iter_mut().enumerate() {
+                let index = (row * width + i) as u64;
+                for s in first_sample..first_sample + samples {
+                    let mut rng = sample_rng(seed, index, s as u64);
+                    let u = (i as f64 + rng.gen::<f64>()) / (width as f64 - 1.0);
+                    let v = (j as f64 + rng.gen::<f64>()) / (height as f64 - 1.0);
+                    let ray = self.camera.get_ray(u, v, &mut rng);
+                    let (world, background, media) = (&self.world, &self.background, &self.media);
+                    *pixel += match integrator {
+                        Integrator::Path => ray_color(&ray, world, background, media, depth, &mut rng),
+                        Integrator::Mis => {
+                            ray_color_mis(&ray, world, &self.lights, background, media, depth, None, &mut rng)
+                        }
+                    };
+                }
+            }
+            on_row();
+        });
+        accum.samples += samples;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::Hittable;
+    use crate::material::DiffuseLight;
+    use crate::render::{Point, RenderRng};
+    use crate::scene::{random_scene, CameraDesc};
+    use crate::sphere::Sphere;
+
+    fn renderer(integrator: Integrator) -> Renderer {
+        let mut scene = Scene::new(CameraDesc::default());
+        scene.world = random_scene(&mut RenderRng::seed_from_u64(7));
+        let light = MatKind::DiffuseLight(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        scene.world.push(Hittable::Sphere(Sphere::new(Point::new(0.0, 4.0, 2.0), 0.5, light)));
+        let settings = RenderSettings { width: 16, height: 9, samples: 6, depth: 8, seed: 0x5eed, integrator };
+        Renderer::new(scene, settings)
+    }
+
+    fn render_in_passes(renderer: &Renderer, passes: &[usize]) -> Accumulation {
+        let mut accum = renderer.accumulation();
+        for &samples in passes {
+            renderer.render_pass(&mut accum, samples);
+        }
+        accum
+    }
+
+    #[test]
+    fn renders_are_reproducible() {
+        for integrator in [Integrator::Path, Integrator::Mis] {
+            let once = render_in_passes(&renderer(integrator), &[6]);
+            assert!(once.sums.iter().any(|c| !c.near_zero()));
+
+            // A fresh renderer, and the same samples split up differently, give
+            // exactly the same sums.
+            let renderer = renderer(integrator);
+            for passes in [&[6][..], &[2, 4], &[1, 1, 1, 3]] {
+                let accum = render_in_passes(&renderer, passes);
+                assert_eq!(accum.samples, 6);
+                assert!(accum.sums == once.sums, "{:?} differs with passes {:?}", integrator, passes);
+            }
+
+            // A different seed gives a different image.
+            let mut other = renderer.accumulation();
+            other.seed += 1;
+            renderer.render_pass(&mut other, 6);
+            assert!(other.sums != once.sums);
+        }
+    }
+}
@@ -0,0 +1,1293 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::background::{Background, EnvironmentMap};
+use crate::camera::Camera;
+use crate::csg::{Csg, CsgOp};
+use crate::cylinder::{Cone, Cylinder};
+use crate::hit::{HitList, Hittable};
+use crate::material::{Bumped, Dielectric, DiffuseLight, Isotropic, Lambertian, MatKind, Metal, NormalMap, Principled};
+use crate::matrix::Matrix4;
+use crate::medium::{ConstantMedium, Fog};
+use crate::mesh::{Mesh, MeshData, MeshFace};
+use crate::moving_sphere::MovingSphere;
+use crate::obj::load_obj;
+use crate::plane::{Disk, Plane};
+use crate::quad::{BoxShape, Quad};
+use crate::rect::{XyRect, XzRect, YzRect};
+use crate::render::{random_f64, Color, Point};
+use crate::sdf::{Sdf, SdfShape};
+use crate::sphere::Sphere;
+use crate::texture::{ImageTexture, Texture};
+use crate::torus::Torus;
+use crate::transform::Transform;
+use crate::triangle::Triangle;
+use crate::vec::Vec3;
+
+use rand::Rng;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+/// A scene as written on disk, either as TOML or JSON. This is deliberately a
+/// plain mirror of the file so that serde can do the heavy lifting and point at
+/// the offending line when something is malformed. Anything that needs cross
+/// referencing (textures and materials are declared once and referred to by
+/// name) is checked afterwards in `SceneFile::build`.
+///
+/// ```toml
+/// [image]
+/// width = 400
+/// samples = 100
+///
+/// [camera]
+/// lookfrom = [13.0, 2.0, 3.0]
+/// lookat = [0.0, 0.0, 0.0]
+/// vfov = 20.0
+/// aperture = 0.1
+///
+/// [materials.ground]
+/// type = "lambertian"
+/// albedo = [0.5, 0.5, 0.5]
+///
+/// [[objects]]
+/// type = "sphere"
+/// center = [0.0, -1000.0, 0.0]
+/// radius = 1000.0
+/// material = "ground"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default)]
+    pub image: ImageDesc,
+    #[serde(default)]
+    pub camera: CameraDesc,
+    #[serde(default, deserialize_with = "BackgroundDesc::deserialize_or_color")]
+    pub background: BackgroundDesc,
+    pub fog: Option<FogDesc>,
+    #[serde(default)]
+    pub textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDesc>,
+}
+
+/// Image settings. Everything is optional here since the CLI has its own
+/// defaults and flags passed on the command line win over the scene file.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageDesc {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples: Option<usize>,
+    pub depth: Option<isize>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    pub lookfrom: [f64; 3],
+    pub lookat: [f64; 3],
+    #[serde(default = "CameraDesc::default_vup")]
+    pub vup: [f64; 3],
+    pub vfov: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    /// Defaults to the distance between `lookfrom` and `lookat`.
+    pub focus_dist: Option<f64>,
+    /// When the shutter opens and closes, for motion blur. Both default to 0,
+    /// an instantaneous exposure.
+    #[serde(default)]
+    pub time0: f64,
+    #[serde(default)]
+    pub time1: f64,
+}
+
+impl CameraDesc {
+    fn default_vup() -> [f64; 3] {
+        [0.0, 1.0, 0.0]
+    }
+
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        let lookfrom = vec3(self.lookfrom);
+        let lookat = vec3(self.lookat);
+        let focus_dist = self.focus_dist.unwrap_or_else(|| (lookfrom - lookat).length());
+        Camera::new(aspect_ratio, self.vfov, lookfrom, lookat, vec3(self.vup), self.aperture, focus_dist)
+            .with_shutter(self.time0, self.time1)
+    }
+}
+
+/// The camera from the cover of the book, used with `random_scene`.
+impl Default for CameraDesc {
+    fn default() -> Self {
+        CameraDesc {
+            lookfrom: [13.0, 2.0, 3.0],
+            lookat: [0.0, 0.0, 0.0],
+            vup: Self::default_vup(),
+            vfov: 20.0,
+            aperture: 0.1,
+            focus_dist: Some(10.0),
+            time0: 0.0,
+            time1: 0.0,
+        }
+    }
+}
+
+/// What rays escaping the scene see. Defaults to the sky from the book.
+///
+/// ```toml
+/// [background]
+/// type = "environment"
+/// path = "sky.hdr"
+/// intensity = 2.0
+/// ```
+///
+/// A bare color, `background = [r, g, b]`, still means a solid background as
+/// it did before the other kinds existed.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BackgroundDesc {
+    #[default]
+    Sky,
+    Solid { color: [f64; 3] },
+    Gradient {
+        bottom: [f64; 3],
+        top: [f64; 3],
+        #[serde(default = "CameraDesc::default_vup")]
+        up: [f64; 3],
+    },
+    /// An equirectangular `.hdr` map, relative to the scene file.
+    Environment {
+        path: PathBuf,
+        #[serde(default = "BackgroundDesc::default_intensity")]
+        intensity: f64,
+    },
+}
+
+impl BackgroundDesc {
+    fn default_intensity() -> f64 {
+        1.0
+    }
+
+    /// Takes either a table, as derived above, or the old bare color. This is
+    /// done by hand rather than with an untagged enum so mistakes in the table
+    /// still get serde's specific error messages.
+    fn deserialize_or_color<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = BackgroundDesc;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a background table or an [r, g, b] color")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                let color = <[f64; 3]>::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(BackgroundDesc::Solid { color })
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                BackgroundDesc::deserialize(de::value::MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+
+    fn build(&self, dir: &Path) -> Result<Background, String> {
+        match self {
+            Self::Sky => Ok(Background::sky()),
+            Self::Solid { color } => Ok(Background::Solid(vec3(*color))),
+            Self::Gradient { bottom, top, up } => {
+                nonzero("background", "up", up)?;
+                Ok(Background::Gradient { bottom: vec3(*bottom), top: vec3(*top), up: vec3(*up) })
+            }
+            Self::Environment { path, intensity } => {
+                let map = EnvironmentMap::load(&dir.join(path), *intensity).map_err(|e| format!("background.path: {}", e))?;
+                Ok(Background::Environment(Arc::new(map)))
+            }
+        }
+    }
+}
+
+/// Fog filling the whole scene. `density` is how often light scatters per unit
+/// length; `albedo` is how much of it survives each time.
+///
+/// ```toml
+/// [fog]
+/// density = 0.02
+/// ```
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FogDesc {
+    pub density: f64,
+    #[serde(default = "TextureDesc::default_color")]
+    pub albedo: [f64; 3],
+}
+
+impl FogDesc {
+    fn build(&self) -> Result<Fog, String> {
+        if self.density <= 0.0 { return Err(format!("fog.density: must be positive, got {}", self.density)) }
+        Ok(Fog::new(self.density, vec3(self.albedo)))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDesc {
+    Solid { color: [f64; 3] },
+    /// 3D checkerboard of cubes `scale` units across.
+    Checker {
+        even: [f64; 3],
+        odd: [f64; 3],
+        #[serde(default = "TextureDesc::default_scale")]
+        scale: f64,
+    },
+    /// A PNG, JPEG or `.hdr` image, relative to the scene file. `raw` images
+    /// hold data rather than colors, such as normal maps, and are read as
+    /// stored instead of being gamma decoded.
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        raw: bool,
+    },
+    Noise {
+        #[serde(default = "TextureDesc::default_color")]
+        color: [f64; 3],
+        #[serde(default = "TextureDesc::default_scale")]
+        scale: f64,
+    },
+    Turbulence {
+        #[serde(default = "TextureDesc::default_color")]
+        color: [f64; 3],
+        #[serde(default = "TextureDesc::default_scale")]
+        scale: f64,
+        #[serde(default = "TextureDesc::default_depth")]
+        depth: usize,
+    },
+}
+
+impl TextureDesc {
+    fn default_color() -> [f64; 3] {
+        [1.0, 1.0, 1.0]
+    }
+
+    fn default_scale() -> f64 {
+        1.0
+    }
+
+    fn default_depth() -> usize {
+        7
+    }
+
+    fn build(&self, key: &str, dir: &Path) -> Result<Texture, String> {
+        match *self {
+            Self::Solid { color } => Ok(Texture::Solid(vec3(color))),
+            Self::Checker { even, odd, scale } => {
+                if scale <= 0.0 { return Err(format!("{}.scale: must be positive, got {}", key, scale)) }
+                Ok(Texture::Checker { even: vec3(even), odd: vec3(odd), scale })
+            }
+            Self::Image { ref path, raw } => {
+                let path = dir.join(path);
+                let image = if raw { ImageTexture::load_raw(&path) } else { ImageTexture::load(&path) };
+                let image = image.map_err(|e| format!("{}.path: {}", key, e))?;
+                Ok(Texture::Image(Arc::new(image)))
+            }
+            Self::Noise { color, scale } => Ok(Texture::Noise { color: vec3(color), scale }),
+            Self::Turbulence { color, scale, depth } => Ok(Texture::Turbulence { color: vec3(color), scale, depth }),
+        }
+    }
+}
+
+/// A material color: either given inline or the name of one of the `[textures]`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AlbedoDesc {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+impl AlbedoDesc {
+    fn build(&self, key: &str, textures: &BTreeMap<&str, Texture>) -> Result<Texture, String> {
+        match self {
+            Self::Color(color) => Ok(Texture::Solid(vec3(*color))),
+            Self::Texture(name) => texture(textures, key, name),
+        }
+    }
+}
+
+/// Where a material's shading normals come from, if not the geometry.
+///
+/// ```toml
+/// bump = { type = "normal_map", texture = "bricks_normal" }
+/// bump = { type = "height", texture = "grain", scale = 0.05 }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BumpDesc {
+    /// A tangent space normal map, OpenGL style (green up). Image textures
+    /// holding one should be `raw`.
+    NormalMap { texture: String },
+    /// Bumps `scale` high where the texture is white.
+    Height {
+        texture: String,
+        #[serde(default = "TextureDesc::default_scale")]
+        scale: f64,
+    },
+}
+
+impl BumpDesc {
+    fn build(&self, key: &str, textures: &BTreeMap<&str, Texture>) -> Result<NormalMap, String> {
+        let texture_key = format!("{}.texture", key);
+        match self {
+            Self::NormalMap { texture: name } => Ok(NormalMap::Normal(texture(textures, &texture_key, name)?)),
+            Self::Height { texture: name, scale } => {
+                Ok(NormalMap::Height { height: texture(textures, &texture_key, name)?, scale: *scale })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDesc {
+    Lambertian { albedo: AlbedoDesc, bump: Option<BumpDesc> },
+    /// `roughness` runs from 0, a mirror, to 1.
+    Metal {
+        albedo: AlbedoDesc,
+        #[serde(default)]
+        roughness: f64,
+        bump: Option<BumpDesc>,
+    },
+    /// `tint` colors light going through the surface; `absorption` is how much
+    /// of each channel the interior absorbs per unit length.
+    Dielectric {
+        ir: f64,
+        #[serde(default = "TextureDesc::default_color")]
+        tint: [f64; 3],
+        #[serde(default)]
+        absorption: [f64; 3],
+        bump: Option<BumpDesc>,
+    },
+    DiffuseLight { emit: [f64; 3] },
+    Principled(PrincipledDesc),
+    /// Scatters evenly in all directions; for `constant_medium` objects.
+    Isotropic { albedo: AlbedoDesc },
+}
+
+/// Parameters of the principled material; anything left out gets the same
+/// default as in `Principled::default`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrincipledDesc {
+    pub base_color: AlbedoDesc,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub transmission: f64,
+    pub ior: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub emission: [f64; 3],
+    pub bump: Option<BumpDesc>,
+}
+
+impl Default for PrincipledDesc {
+    fn default() -> Self {
+        let defaults = Principled::default();
+        let color = |c: Color| [c.x, c.y, c.z];
+        PrincipledDesc {
+            base_color: AlbedoDesc::Color([0.8, 0.8, 0.8]),
+            metallic: defaults.metallic,
+            roughness: defaults.roughness,
+            specular: defaults.specular,
+            transmission: defaults.transmission,
+            ior: defaults.ior,
+            clearcoat: defaults.clearcoat,
+            clearcoat_roughness: defaults.clearcoat_roughness,
+            emission: color(defaults.emission),
+            bump: None,
+        }
+    }
+}
+
+impl PrincipledDesc {
+    fn build(&self, key: &str, textures: &BTreeMap<&str, Texture>) -> Result<Principled, String> {
+        let fractions = [
+            ("metallic", self.metallic),
+            ("roughness", self.roughness),
+            ("specular", self.specular),
+            ("transmission", self.transmission),
+            ("clearcoat", self.clearcoat),
+            ("clearcoat_roughness", self.clearcoat_roughness),
+        ];
+        for (name, value) in fractions {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{}.{}: must be between 0 and 1, got {}", key, name, value))
+            }
+        }
+        if self.ior <= 0.0 { return Err(format!("{}.ior: index of refraction must be positive, got {}", key, self.ior)) }
+
+        Ok(Principled {
+            base_color: self.base_color.build(&format!("{}.base_color", key), textures)?,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            specular: self.specular,
+            transmission: self.transmission,
+            ior: self.ior,
+            clearcoat: self.clearcoat,
+            clearcoat_roughness: self.clearcoat_roughness,
+            emission: vec3(self.emission),
+        })
+    }
+}
+
+impl MaterialDesc {
+    fn build(&self, key: &str, textures: &BTreeMap<&str, Texture>) -> Result<MatKind, String> {
+        let material = match *self {
+            Self::Lambertian { ref albedo, .. } => {
+                MatKind::Lambertian(Lambertian::new(albedo.build(&format!("{}.albedo", key), textures)?))
+            }
+            Self::Metal { ref albedo, roughness, .. } => {
+                if !(0.0..=1.0).contains(&roughness) {
+                    return Err(format!("{}.roughness: must be between 0 and 1, got {}", key, roughness))
+                }
+                MatKind::Metal(Metal::new(albedo.build(&format!("{}.albedo", key), textures)?, roughness))
+            }
+            Self::Dielectric { ir, tint, absorption, .. } => {
+                if ir <= 0.0 { return Err(format!("{}.ir: index of refraction must be positive, got {}", key, ir)) }
+                if absorption.iter().any(|&a| a < 0.0) {
+                    return Err(format!("{}.absorption: must not be negative, got {:?}", key, absorption))
+                }
+                MatKind::Dielectric(Dielectric::tinted(ir, vec3(tint), vec3(absorption)))
+            }
+            Self::DiffuseLight { emit } => MatKind::DiffuseLight(DiffuseLight::new(vec3(emit))),
+            Self::Principled(ref desc) => MatKind::Principled(desc.build(key, textures)?),
+            Self::Isotropic { ref albedo } => {
+                MatKind::Isotropic(Isotropic::new(albedo.build(&format!("{}.albedo", key), textures)?))
+            }
+        };
+
+        let bump = match self {
+            Self::Lambertian { bump, .. } | Self::Metal { bump, .. } | Self::Dielectric { bump, .. } => bump,
+            Self::Principled(desc) => &desc.bump,
+            Self::DiffuseLight { .. } | Self::Isotropic { .. } => &None,
+        };
+        match bump {
+            Some(bump) => {
+                let map = bump.build(&format!("{}.bump", key), textures)?;
+                Ok(MatKind::Bumped(Box::new(Bumped::new(material, map))))
+            }
+            None => Ok(material),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDesc {
+    /// A negative `radius` turns the normals inward, the book's trick for
+    /// hollow glass spheres.
+    Sphere { center: [f64; 3], radius: f64, material: String },
+    /// A sphere moving from `center0` at `time0` to `center1` at `time1`.
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "ObjectDesc::default_time1")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        material: String,
+    },
+    /// An indexed triangle mesh. `normals` and `uvs`, if given, are per vertex
+    /// and share the indices in `faces` with `positions`.
+    Mesh {
+        positions: Vec<[f64; 3]>,
+        normals: Option<Vec<[f64; 3]>>,
+        uvs: Option<Vec<[f64; 2]>>,
+        faces: Vec<[usize; 3]>,
+        material: String,
+    },
+    /// Rectangles facing +z, +y and +x respectively, at `k` along that axis.
+    XyRect { x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: String },
+    XzRect { x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: String },
+    YzRect { y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: String },
+    /// A parallelogram with a corner at `q` and sides `u` and `v`, facing
+    /// along `u x v`.
+    Quad { q: [f64; 3], u: [f64; 3], v: [f64; 3], material: String },
+    /// An axis-aligned box between the opposite corners `min` and `max`.
+    #[serde(rename = "box")]
+    BoxShape { min: [f64; 3], max: [f64; 3], material: String },
+    /// An infinite plane through `point`, facing along `normal`.
+    Plane { point: [f64; 3], normal: [f64; 3], material: String },
+    Disk { center: [f64; 3], normal: [f64; 3], radius: f64, material: String },
+    /// Closed at both ends.
+    Cylinder { base: [f64; 3], top: [f64; 3], radius: f64, material: String },
+    /// Closed at the base.
+    Cone { base: [f64; 3], apex: [f64; 3], radius: f64, material: String },
+    Torus {
+        center: [f64; 3],
+        #[serde(default = "CameraDesc::default_vup")]
+        axis: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+    },
+    /// A Wavefront OBJ file, relative to the scene file. Uses the materials from
+    /// the file's MTL libraries unless `material` is given.
+    Obj { path: PathBuf, material: Option<String> },
+    /// Smoke or fog of constant `density` filling `boundary`, which must be a
+    /// single closed object. The boundary's own material is never seen; the
+    /// volume's has to be `isotropic`. Volumes can be transformed but not be
+    /// part of other objects.
+    ConstantMedium { boundary: Box<ObjectDesc>, density: f64, material: String },
+    /// The union, intersection or difference (`left` minus `right`) of two
+    /// closed objects.
+    Csg { operation: CsgOpDesc, left: Box<ObjectDesc>, right: Box<ObjectDesc> },
+    /// A shape given by a signed distance function, built up from `shape`.
+    Sdf { shape: SdfDesc, material: String },
+    /// `object` placed by the steps in `transform`, applied in order.
+    Transform { object: Box<ObjectDesc>, transform: Vec<TransformDesc> },
+    /// Copies of `object` sharing its geometry, one per list of steps.
+    Instances { object: Box<ObjectDesc>, transforms: Vec<Vec<TransformDesc>> },
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOpDesc {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// A node in the tree of distance functions making up an `sdf` object. The
+/// boolean operators blend their two shapes over `smoothness` where they meet,
+/// or not at all when it's 0.
+///
+/// ```toml
+/// shape = { type = "union", smoothness = 0.3,
+///           left = { type = "sphere", center = [0.0, 1.0, 0.0], radius = 1.0 },
+///           right = { type = "box", center = [0.0, 0.0, 0.0], half_extents = [1.5, 0.2, 1.5] } }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SdfDesc {
+    Sphere { center: [f64; 3], radius: f64 },
+    /// Axis-aligned, `2 * half_extents` across.
+    Box { center: [f64; 3], half_extents: [f64; 3] },
+    /// Lying flat in the xz plane.
+    Torus { center: [f64; 3], major_radius: f64, minor_radius: f64 },
+    /// Standing along y.
+    Cylinder { center: [f64; 3], radius: f64, half_height: f64 },
+    Mandelbulb {
+        center: [f64; 3],
+        radius: f64,
+        #[serde(default = "SdfDesc::default_power")]
+        power: f64,
+        #[serde(default = "SdfDesc::default_iterations")]
+        iterations: usize,
+    },
+    /// `shape` grown by `radius`, with its edges rounded off.
+    Round { shape: Box<SdfDesc>, radius: f64 },
+    Union { left: Box<SdfDesc>, right: Box<SdfDesc>, #[serde(default)] smoothness: f64 },
+    Intersection { left: Box<SdfDesc>, right: Box<SdfDesc>, #[serde(default)] smoothness: f64 },
+    /// `left` minus `right`.
+    Difference { left: Box<SdfDesc>, right: Box<SdfDesc>, #[serde(default)] smoothness: f64 },
+}
+
+impl SdfDesc {
+    fn default_power() -> f64 {
+        8.0
+    }
+
+    fn default_iterations() -> usize {
+        12
+    }
+
+    fn build(&self, key: &str) -> Result<SdfShape, String> {
+        let operands = |left: &SdfDesc, right: &SdfDesc, smoothness: f64| -> Result<_, String> {
+            if smoothness < 0.0 {
+                return Err(format!("{}.smoothness: must not be negative, got {}", key, smoothness))
+            }
+            let left = Box::new(left.build(&format!("{}.left", key))?);
+            let right = Box::new(right.build(&format!("{}.right", key))?);
+            Ok((left, right))
+        };
+        Ok(match self {
+            Self::Sphere { center, radius } => {
+                positive(key, "radius", *radius)?;
+                SdfShape::Sphere { center: vec3(*center), radius: *radius }
+            }
+            Self::Box { center, half_extents } => {
+                if half_extents.iter().any(|&e| e <= 0.0) {
+                    return Err(format!("{}.half_extents: must be positive, got {:?}", key, half_extents))
+                }
+                SdfShape::Cuboid { center: vec3(*center), half_extents: vec3(*half_extents) }
+            }
+            Self::Torus { center, major_radius, minor_radius } => {
+                positive(key, "major_radius", *major_radius)?;
+                positive(key, "minor_radius", *minor_radius)?;
+                SdfShape::Torus { center: vec3(*center), major_radius: *major_radius, minor_radius: *minor_radius }
+            }
+            Self::Cylinder { center, radius, half_height } => {
+                positive(key, "radius", *radius)?;
+                positive(key, "half_height", *half_height)?;
+                SdfShape::Cylinder { center: vec3(*center), radius: *radius, half_height: *half_height }
+            }
+            Self::Mandelbulb { center, radius, power, iterations } => {
+                positive(key, "radius", *radius)?;
+                if *power < 2.0 { return Err(format!("{}.power: must be at least 2, got {}", key, power)) }
+                SdfShape::Mandelbulb { center: vec3(*center), radius: *radius, power: *power, iterations: *iterations }
+            }
+            Self::Round { shape, radius } => {
+                positive(key, "radius", *radius)?;
+                SdfShape::Round(Box::new(shape.build(&format!("{}.shape", key))?), *radius)
+            }
+            Self::Union { left, right, smoothness } => match operands(left, right, *smoothness)? {
+                (a, b) if *smoothness > 0.0 => SdfShape::SmoothUnion(a, b, *smoothness),
+                (a, b) => SdfShape::Union(a, b),
+            },
+            Self::Intersection { left, right, smoothness } => match operands(left, right, *smoothness)? {
+                (a, b) if *smoothness > 0.0 => SdfShape::SmoothIntersection(a, b, *smoothness),
+                (a, b) => SdfShape::Intersection(a, b),
+            },
+            Self::Difference { left, right, smoothness } => match operands(left, right, *smoothness)? {
+                (a, b) if *smoothness > 0.0 => SdfShape::SmoothDifference(a, b, *smoothness),
+                (a, b) => SdfShape::Difference(a, b),
+            },
+        })
+    }
+}
+
+/// One step in placing an object.
+///
+/// ```toml
+/// transform = [{ scale = [1.0, 2.0, 1.0] }, { rotate = { axis = [0.0, 1.0, 0.0], angle = 45.0 } }]
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformDesc {
+    Translate([f64; 3]),
+    Scale([f64; 3]),
+    /// `angle` degrees about `axis` through the origin.
+    Rotate { axis: [f64; 3], angle: f64 },
+    /// A full affine matrix, by rows.
+    Matrix([[f64; 4]; 4]),
+}
+
+impl TransformDesc {
+    /// The matrix for `steps`, applied first to last.
+    fn build(key: &str, steps: &[TransformDesc]) -> Result<Matrix4, String> {
+        let mut matrix = Matrix4::IDENTITY;
+        for (i, step) in steps.iter().enumerate() {
+            let m = match *step {
+                Self::Translate(offset) => Matrix4::translation(vec3(offset)),
+                Self::Scale(factors) => Matrix4::scaling(vec3(factors)),
+                Self::Rotate { axis, angle } => {
+                    if vec3(axis).near_zero() { return Err(format!("{}[{}].rotate.axis: must not be zero", key, i)) }
+                    Matrix4::rotation(vec3(axis), angle)
+                }
+                Self::Matrix(m) => Matrix4::new(m),
+            };
+            matrix = m * matrix;
+        }
+        if matrix.inverse().is_none() { return Err(format!("{}: transform can't be inverted", key)) }
+        Ok(matrix)
+    }
+}
+
+impl ObjectDesc {
+    fn default_time1() -> f64 {
+        1.0
+    }
+
+    /// Builds the object and adds it to `world`. Relative paths are resolved
+    /// against `dir`, the directory holding the scene file.
+    fn build(
+        &self,
+        key: &str,
+        materials: &BTreeMap<&str, MatKind>,
+        dir: &Path,
+        world: &mut HitList<MatKind>) -> Result<(), String>
+    {
+        match self {
+            Self::Sphere { center, radius, material } => {
+                let mat = lookup(materials, key, material)?;
+                nonzero_radius(key, *radius)?;
+                world.push(Hittable::Sphere(Sphere::new(vec3(*center), *radius, mat)));
+            }
+            Self::MovingSphere { center0, center1, time0, time1, radius, material } => {
+                let mat = lookup(materials, key, material)?;
+                nonzero_radius(key, *radius)?;
+                let sphere = MovingSphere::new(vec3(*center0), vec3(*center1), *time0, *time1, *radius, mat);
+                world.push(Hittable::MovingSphere(sphere));
+            }
+            Self::Triangle { vertices, normals, uvs, material } => {
+                let mat = lookup(materials, key, material)?;
+                let vertices = vertices.map(vec3);
+                let normals = normals.map(|n| n.map(vec3));
+                let uvs = uvs.map(|uv| uv.map(|[u, v]| (u, v)));
+                world.push(Hittable::Triangle(Triangle::new(vertices, normals, uvs, mat)));
+            }
+            Self::Mesh { positions, normals, uvs, faces, material } => {
+                let mat = lookup(materials, key, material)?;
+                let n = positions.len();
+                for (name, len) in [("normals", normals.as_ref().map(Vec::len)), ("uvs", uvs.as_ref().map(Vec::len))] {
+                    if let Some(len) = len {
+                        if len != n {
+                            return Err(format!("{}.{}: expected one per position ({}), got {}", key, name, n, len))
+                        }
+                    }
+                }
+                for (i, face) in faces.iter().enumerate() {
+                    if let Some(index) = face.iter().find(|&&index| index >= n) {
+                        return Err(format!("{}.faces[{}]: index {} is out of range for {} positions", key, i, index, n))
+                    }
+                }
+
+                let data = MeshData {
+                    positions: positions.iter().copied().map(vec3).collect(),
+                    normals: normals.iter().flatten().copied().map(vec3).collect(),
+                    uvs: uvs.iter().flatten().map(|&[u, v]| (u, v)).collect(),
+                };
+                let faces = faces
+                    .iter()
+                    .map(|&face| MeshFace::new(face, normals.as_ref().map(|_| face), uvs.as_ref().map(|_| face)))
+                    .collect();
+                world.push(Hittable::Mesh(Mesh::new(Arc::new(data), faces, mat)));
+            }
+            Self::XyRect { x0, x1, y0, y1, k, material } => {
+                let mat = lookup(materials, key, material)?;
+                if x1 <= x0 || y1 <= y0 { return Err(format!("{}: needs x0 < x1 and y0 < y1", key)) }
+                world.push(Hittable::XyRect(XyRect::new(*x0, *x1, *y0, *y1, *k, mat)));
+            }
+            Self::XzRect { x0, x1, z0, z1, k, material } => {
+                let mat = lookup(materials, key, material)?;
+                if x1 <= x0 || z1 <= z0 { return Err(format!("{}: needs x0 < x1 and z0 < z1", key)) }
+                world.push(Hittable::XzRect(XzRect::new(*x0, *x1, *z0, *z1, *k, mat)));
+            }
+            Self::YzRect { y0, y1, z0, z1, k, material } => {
+                let mat = lookup(materials, key, material)?;
+                if y1 <= y0 || z1 <= z0 { return Err(format!("{}: needs y0 < y1 and z0 < z1", key)) }
+                world.push(Hittable::YzRect(YzRect::new(*y0, *y1, *z0, *z1, *k, mat)));
+            }
+            Self::Quad { q, u, v, material } => {
+                let mat = lookup(materials, key, material)?;
+                if vec3(*u).cross(&vec3(*v)).near_zero() {
+                    return Err(format!("{}: sides u and v must not be parallel", key))
+                }
+                world.push(Hittable::Quad(Quad::new(vec3(*q), vec3(*u), vec3(*v), mat)));
+            }
+            Self::BoxShape { min, max, material } => {
+                let mat = lookup(materials, key, material)?;
+                world.push(Hittable::BoxShape(Box::new(BoxShape::new(vec3(*min), vec3(*max), mat))));
+            }
+            Self::Plane { point, normal, material } => {
+                let mat = lookup(materials, key, material)?;
+                nonzero(key, "normal", normal)?;
+                world.push(Hittable::Plane(Plane::new(vec3(*point), vec3(*normal), mat)));
+            }
+            Self::Disk { center, normal, radius, material } => {
+                let mat = lookup(materials, key, material)?;
+                nonzero(key, "normal", normal)?;
+                positive(key, "radius", *radius)?;
+                world.push(Hittable::Disk(Disk::new(vec3(*center), vec3(*normal), *radius, mat)));
+            }
+            Self::Cylinder { base, top, radius, material } => {
+                let mat = lookup(materials, key, material)?;
+                if base == top { return Err(format!("{}.top: must differ from base", key)) }
+                positive(key, "radius", *radius)?;
+                world.push(Hittable::Cylinder(Cylinder::new(vec3(*base), vec3(*top), *radius, mat)));
+            }
+            Self::Cone { base, apex, radius, material } => {
+                let mat = lookup(materials, key, material)?;
+                if base == apex { return Err(format!("{}.apex: must differ from base", key)) }
+                positive(key, "radius", *radius)?;
+                world.push(Hittable::Cone(Cone::new(vec3(*base), vec3(*apex), *radius, mat)));
+            }
+            Self::Torus { center, axis, major_radius, minor_radius, material } => {
+                let mat = lookup(materials, key, material)?;
+                nonzero(key, "axis", axis)?;
+                positive(key, "major_radius", *major_radius)?;
+                positive(key, "minor_radius", *minor_radius)?;
+                if minor_radius >= major_radius {
+                    return Err(format!("{}.minor_radius: must be less than major_radius, got {}", key, minor_radius))
+                }
+                let torus = Torus::new(vec3(*center), vec3(*axis), *major_radius, *minor_radius, mat);
+                world.push(Hittable::Torus(torus));
+            }
+            Self::Obj { path, material } => {
+                let mat = material.as_ref().map(|name| lookup(materials, key, name)).transpose()?;
+                let meshes = load_obj(&dir.join(path), mat).map_err(|e| format!("{}.path: {}", key, e))?;
+                for mesh in meshes {
+                    world.push(Hittable::Mesh(mesh));
+                }
+            }
+            Self::ConstantMedium { boundary, density, material } => {
+                let mat = lookup(materials, key, material)?;
+                // Volume hits have no surface, which only the phase function copes with.
+                if !matches!(mat, MatKind::Isotropic(_)) {
+                    return Err(format!("{}.material: \"{}\" must be isotropic to fill a volume", key, material))
+                }
+                if *density <= 0.0 { return Err(format!("{}.density: must be positive, got {}", key, density)) }
+                let boundary = boundary.build_single(&format!("{}.boundary", key), materials, dir)?;
+                world.push(Hittable::ConstantMedium(ConstantMedium::new(boundary, *density, mat)));
+            }
+            Self::Csg { operation, left, right } => {
+                let left = left.build_single(&format!("{}.left", key), materials, dir)?;
+                let right = right.build_single(&format!("{}.right", key), materials, dir)?;
+                let op = match operation {
+                    CsgOpDesc::Union => CsgOp::Union,
+                    CsgOpDesc::Intersection => CsgOp::Intersection,
+                    CsgOpDesc::Difference => CsgOp::Difference,
+                };
+                world.push(Hittable::Csg(Csg::new(op, left, right)));
+            }
+            Self::Sdf { shape, material } => {
+                let mat = lookup(materials, key, material)?;
+                let shape = shape.build(&format!("{}.shape", key))?;
+                world.push(Hittable::Sdf(Sdf::new(shape, mat)));
+            }
+            Self::Transform { object, transform } => {
+                let matrix = TransformDesc::build(&format!("{}.transform", key), transform)?;
+                instance(key, object, &[matrix], materials, dir, world)?;
+            }
+            Self::Instances { object, transforms } => {
+                let matrices = transforms
+                    .iter()
+                    .enumerate()
+                    .map(|(i, steps)| TransformDesc::build(&format!("{}.transforms[{}]", key, i), steps))
+                    .collect::<Result<Vec<_>, _>>()?;
+                instance(key, object, &matrices, materials, dir, world)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds an object that has to come out as exactly one hittable, as the
+    /// parts of other objects do.
+    fn build_single(
+        &self,
+        key: &str,
+        materials: &BTreeMap<&str, MatKind>,
+        dir: &Path) -> Result<Hittable<MatKind>, String>
+    {
+        let mut inner = HitList::new();
+        self.build(key, materials, dir, &mut inner)?;
+        let mut inner = inner.into_inner();
+        if inner.len() != 1 { return Err(format!("{}: must be a single object, got {}", key, inner.len())) }
+        // Volumes are only sampled at the top level of the scene (see `Media`).
+        if is_volume(&inner[0]) { return Err(format!("{}: a constant_medium can't be part of another object", key)) }
+        Ok(inner.remove(0))
+    }
+}
+
+/// Builds `object` once and adds a transformed copy of it to `world` for each
+/// of `matrices`. The copies all share the one object.
+fn instance(
+    key: &str,
+    object: &ObjectDesc,
+    matrices: &[Matrix4],
+    materials: &BTreeMap<&str, MatKind>,
+    dir: &Path,
+    world: &mut HitList<MatKind>) -> Result<(), String>
+{
+    let mut inner = HitList::new();
+    object.build(&format!("{}.object", key), materials, dir, &mut inner)?;
+    let inner: Vec<_> = inner.into_inner().into_iter().map(Arc::new).collect();
+    for matrix in matrices {
+        for hittable in inner.iter() {
+            let transform = Transform::new(Arc::clone(hittable), *matrix).expect("checked by TransformDesc::build");
+            world.push(Hittable::Transform(Box::new(transform)));
+        }
+    }
+    Ok(())
+}
+
+fn is_volume(hittable: &Hittable<MatKind>) -> bool {
+    match hittable {
+        Hittable::ConstantMedium(_) => true,
+        Hittable::Transform(transform) => is_volume(&transform.object),
+        _ => false,
+    }
+}
+
+fn positive(key: &str, name: &str, value: f64) -> Result<(), String> {
+    if value > 0.0 { Ok(()) } else { Err(format!("{}.{}: must be positive, got {}", key, name, value)) }
+}
+
+fn nonzero_radius(key: &str, radius: f64) -> Result<(), String> {
+    if radius != 0.0 && radius.is_finite() { return Ok(()) }
+    Err(format!("{}.radius: must be non-zero, got {}", key, radius))
+}
+
+fn nonzero(key: &str, name: &str, value: &[f64; 3]) -> Result<(), String> {
+    if vec3(*value).near_zero() { Err(format!("{}.{}: must not be zero", key, name)) } else { Ok(()) }
+}
+
+fn texture(textures: &BTreeMap<&str, Texture>, key: &str, name: &str) -> Result<Texture, String> {
+    textures.get(name).cloned().ok_or_else(|| format!("{}: unknown texture \"{}\"", key, name))
+}
+
+fn lookup(materials: &BTreeMap<&str, MatKind>, key: &str, name: &str) -> Result<MatKind, String> {
+    materials.get(name).cloned().ok_or_else(|| format!("{}.material: unknown material \"{}\"", key, name))
+}
+
+/// Everything needed to render, resolved from a `SceneFile`.
+pub struct Scene {
+    pub image: ImageDesc,
+    pub camera: CameraDesc,
+    pub background: Background,
+    pub fog: Option<Fog>,
+    pub world: HitList<MatKind>,
+}
+
+impl SceneFile {
+    /// Reads a scene, picking the parser from the file extension.
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        let text = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+        let parse_err = |message: String| SceneError::Parse(path.to_path_buf(), message);
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(|e| parse_err(e.to_string())),
+            Some("json") => serde_json::from_str(&text).map_err(|e| parse_err(e.to_string())),
+            _ => Err(SceneError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    /// Resolves material names and builds the world.
+    pub fn build(self, path: &Path) -> Result<Scene, SceneError> {
+        let invalid = |message: String| SceneError::Invalid(path.to_path_buf(), message);
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut textures = BTreeMap::new();
+        for (name, desc) in self.textures.iter() {
+            let texture = desc.build(&format!("textures.{}", name), dir).map_err(invalid)?;
+            textures.insert(name.as_str(), texture);
+        }
+
+        let mut materials = BTreeMap::new();
+        for (name, desc) in self.materials.iter() {
+            let mat = desc.build(&format!("materials.{}", name), &textures).map_err(invalid)?;
+            materials.insert(name.as_str(), mat);
+        }
+
+        let background = self.background.build(dir).map_err(invalid)?;
+        let fog = self.fog.map(|fog| fog.build()).transpose().map_err(invalid)?;
+        let mut world = HitList::new();
+        for (i, obj) in self.objects.iter().enumerate() {
+            obj.build(&format!("objects[{}]", i), &materials, dir, &mut world).map_err(invalid)?;
+        }
+
+        for (key, value) in [("width", self.image.width), ("height", self.image.height), ("samples", self.image.samples)] {
+            if value == Some(0) { return Err(invalid(format!("image.{}: must be greater than zero", key))) }
+        }
+        if let Some(depth) = self.image.depth.filter(|&depth| depth <= 0) {
+            return Err(invalid(format!("image.depth: must be greater than zero, got {}", depth)))
+        }
+        let CameraDesc { time0, time1, .. } = self.camera;
+        if time1 < time0 { return Err(invalid(format!("camera.time1: must not be before time0 ({}), got {}", time0, time1))) }
+        if !(self.camera.vfov > 0.0 && self.camera.vfov < 180.0) {
+            return Err(invalid(format!("camera.vfov: must be between 0 and 180 degrees, got {}", self.camera.vfov)))
+        }
+
+        Ok(Scene { image: self.image, camera: self.camera, background, fog, world })
+    }
+}
+
+impl Scene {
+    /// An empty scene under the book's sky, to be filled with `add`.
+    pub fn new(camera: CameraDesc) -> Self {
+        Scene { image: ImageDesc::default(), camera, background: Background::sky(), fog: None, world: HitList::new() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        SceneFile::load(path)?.build(path)
+    }
+
+    pub fn add(&mut self, hittable: Hittable<MatKind>) {
+        self.world.push(hittable)
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    /// Syntax or type errors; the message from the parser carries the line and column.
+    Parse(PathBuf, String),
+    /// The file parsed, but doesn't describe a valid scene. The message starts
+    /// with the offending key.
+    Invalid(PathBuf, String),
+    UnknownFormat(PathBuf),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "could not read scene {}: {}", path.display(), e),
+            Self::Parse(path, message) => write!(f, "error parsing scene {}: {}", path.display(), message),
+            Self::Invalid(path, message) => write!(f, "invalid scene {}: {}", path.display(), message),
+            Self::UnknownFormat(path) => {
+                write!(f, "unknown scene format for {}: expected a .toml or .json file", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[inline]
+fn vec3(a: [f64; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+/// This generates a random scene using the same business logic as for the scene on the cover of the book.
+/// We make several different small spheres, somewhat randomly positioning them and assigning them a material.
+pub fn random_scene<R: Rng + ?Sized>(rng: &mut R) -> HitList<MatKind> {
+    let mut world: HitList<MatKind> = HitList::new();
+    
+    let ground = MatKind::Lambertian(Lambertian::new(Color::new(0.5117, 0.2539, 0.0977)));
+    world.push(Hittable::Sphere(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.gen::<f64>();
+            let center = Point::new(a as f64 + 0.9 * rng.gen::<f64>(), 0.2, b as f64 + 0.9 * rng.gen::<f64>());
+
+            if (center - Point::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_mat: MatKind;
+                if choose_mat < 0.6 {
+                    // diffuse
+                    let albedo = Color::random(0.0, 1.0, rng) * Color::random(0.0, 1.0, rng);
+                    sphere_mat = MatKind::Lambertian(Lambertian::new(albedo));
+                    world.push(Hittable::Sphere(Sphere::new(center, 0.2, sphere_mat)));
+                } else if choose_mat < 0.8 {
+                    // metal
+                    let albedo = Color::random(0.2, 1.0, rng);
+                    let fuzz = random_f64(0.0, 0.5, rng);
+                    sphere_mat = MatKind::Metal(Metal::new(albedo, fuzz));
+                    world.push(Hittable::Sphere(Sphere::new(center, 0.2, sphere_mat)));
+                } else {
+                    // glass
+                    sphere_mat = MatKind::Dielectric(Dielectric::new(1.5));
+                    world.push(Hittable::Sphere(Sphere::new(center, 0.2, sphere_mat)));
+                }
+            }
+        }
+    }
+
+    let mat1 = MatKind::Dielectric(Dielectric::new(1.5));
+    world.push(Hittable::Sphere(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, mat1)));
+
+    let mat2 = MatKind::Lambertian(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.push(Hittable::Sphere(Sphere::new(Point::new(-4.0, 1.0, 0.0), 1.0, mat2)));
+
+    let mat3 = MatKind::Metal(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    world.push(Hittable::Sphere(Sphere::new(Point::new(4.0, 1.0, 0.0), 1.0, mat3)));
+
+    world
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The message from building `text` as a TOML scene, which must fail.
+    fn error(text: &str) -> String {
+        let file: SceneFile = toml::from_str(text).expect("scene should parse");
+        match file.build(Path::new("test.toml")) {
+            Ok(_) => panic!("scene should be invalid"),
+            Err(SceneError::Invalid(_, message)) => message,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    const MATERIALS: &str = r#"
+        [materials.grey]
+        type = "lambertian"
+        albedo = [0.5, 0.5, 0.5]
+    "#;
+
+    #[test]
+    fn errors_name_the_offending_key() {
+        let objects = r#"
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 1.0
+            material = "grey"
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 1.0
+            material = "gray"
+        "#;
+        assert_eq!(error(&format!("{}{}", MATERIALS, objects)), "objects[1].material: unknown material \"gray\"");
+
+        let sphere = r#"
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 0.0
+            material = "grey"
+        "#;
+        assert_eq!(error(&format!("{}{}", MATERIALS, sphere)), "objects[0].radius: must be non-zero, got 0");
+
+        let torus = r#"
+            [[objects]]
+            type = "torus"
+            center = [0.0, 0.0, 0.0]
+            major_radius = 1.0
+            minor_radius = 1.5
+            material = "grey"
+        "#;
+        let expected = "objects[0].minor_radius: must be less than major_radius, got 1.5";
+        assert_eq!(error(&format!("{}{}", MATERIALS, torus)), expected);
+
+        let metal = r#"
+            [materials.steel]
+            type = "metal"
+            albedo = [0.5, 0.5, 0.5]
+            roughness = 2.0
+        "#;
+        assert_eq!(error(metal), "materials.steel.roughness: must be between 0 and 1, got 2");
+
+        let texture = r#"
+            [materials.wood]
+            type = "lambertian"
+            albedo = "grain"
+        "#;
+        assert_eq!(error(texture), "materials.wood.albedo: unknown texture \"grain\"");
+    }
+
+    #[test]
+    fn errors_in_nested_objects_give_the_full_path() {
+        let csg = r#"
+            [[objects]]
+            type = "csg"
+            operation = "difference"
+            left = { type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = "grey" }
+
+            [objects.right]
+            type = "disk"
+            center = [0.0, 0.0, 0.0]
+            normal = [0.0, 1.0, 0.0]
+            radius = -1.0
+            material = "grey"
+        "#;
+        assert_eq!(error(&format!("{}{}", MATERIALS, csg)), "objects[0].right.radius: must be positive, got -1");
+
+        let sdf = r#"
+            [[objects]]
+            type = "sdf"
+            material = "grey"
+
+            [objects.shape]
+            type = "union"
+            left = { type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0 }
+
+            [objects.shape.right]
+            type = "round"
+            radius = 0.1
+            shape = { type = "torus", center = [0.0, 0.0, 0.0], major_radius = 0.0, minor_radius = 0.1 }
+        "#;
+        let expected = "objects[0].shape.right.shape.major_radius: must be positive, got 0";
+        assert_eq!(error(&format!("{}{}", MATERIALS, sdf)), expected);
+    }
+
+    #[test]
+    fn image_settings_must_be_positive() {
+        assert_eq!(error("[image]\nsamples = 0"), "image.samples: must be greater than zero");
+        assert_eq!(error("[image]\ndepth = -1"), "image.depth: must be greater than zero, got -1");
+    }
+
+    #[test]
+    fn background_takes_a_table_or_a_bare_color() {
+        let file: SceneFile = toml::from_str("background = [0.1, 0.2, 0.3]").unwrap();
+        assert!(matches!(file.background, BackgroundDesc::Solid { color } if color == [0.1, 0.2, 0.3]));
+        let file: SceneFile = serde_json::from_str(r#"{ "background": [0.1, 0.2, 0.3] }"#).unwrap();
+        assert!(matches!(file.background, BackgroundDesc::Solid { color } if color == [0.1, 0.2, 0.3]));
+
+        let file: SceneFile = toml::from_str("[background]\ntype = \"solid\"\ncolor = [1.0, 1.0, 1.0]").unwrap();
+        assert!(matches!(file.background, BackgroundDesc::Solid { color } if color == [1.0, 1.0, 1.0]));
+        let file: SceneFile = toml::from_str("").unwrap();
+        assert!(matches!(file.background, BackgroundDesc::Sky));
+
+        // Mistakes in either form are still reported precisely.
+        let e = toml::from_str::<SceneFile>("[background]\ntype = \"solid\"\ncolour = [1.0, 1.0, 1.0]").unwrap_err();
+        assert!(e.to_string().contains("unknown field `colour`"), "{}", e);
+        assert!(toml::from_str::<SceneFile>("background = [0.1, 0.2]").is_err());
+
+        let gradient = "[background]\ntype = \"gradient\"\nbottom = [1.0, 1.0, 1.0]\ntop = [0.0, 0.0, 0.0]\nup = [0.0, 0.0, 0.0]";
+        assert_eq!(error(gradient), "background.up: must not be zero");
+    }
+
+    #[test]
+    fn volumes_need_an_isotropic_material_and_the_top_level() {
+        let materials = r#"
+            [materials.glass]
+            type = "dielectric"
+            ir = 1.5
+
+            [materials.smoke]
+            type = "isotropic"
+            albedo = [0.8, 0.8, 0.8]
+        "#;
+        let glass = r#"
+            [[objects]]
+            type = "constant_medium"
+            density = 1.0
+            material = "glass"
+            boundary = { type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = "smoke" }
+        "#;
+        let expected = "objects[0].material: \"glass\" must be isotropic to fill a volume";
+        assert_eq!(error(&format!("{}{}", materials, glass)), expected);
+
+        let csg = r#"
+            [[objects]]
+            type = "csg"
+            operation = "union"
+            left = { type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = "glass" }
+
+            [objects.right]
+            type = "constant_medium"
+            density = 1.0
+            material = "smoke"
+            boundary = { type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = "smoke" }
+        "#;
+        let expected = "objects[0].right: a constant_medium can't be part of another object";
+        assert_eq!(error(&format!("{}{}", materials, csg)), expected);
+
+        // Transformed volumes are fine at the top level.
+        let transformed = r#"
+            [[objects]]
+            type = "transform"
+            transform = [{ translate = [1.0, 0.0, 0.0] }]
+
+            [objects.object]
+            type = "constant_medium"
+            density = 1.0
+            material = "smoke"
+            boundary = { type = "sphere", center = [0.0, 0.0, 0.0], radius = 1.0, material = "smoke" }
+        "#;
+        let file: SceneFile = toml::from_str(&format!("{}{}", materials, transformed)).unwrap();
+        assert!(file.build(Path::new("test.toml")).is_ok());
+    }
+}
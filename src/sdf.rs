@@ -0,0 +1,246 @@
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HitRecord};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::sphere::{sphere_derivatives, sphere_uv};
+use crate::vec::Vec3;
+
+/// A shape given by its signed distance function: negative inside, positive
+/// outside, and never more than the distance to the surface, so a ray can
+/// safely step that far. Primitives combine into trees with the boolean and
+/// smooth blending operators; everything is bounded, so every shape has a box.
+#[derive(Clone, Debug)]
+pub enum SdfShape {
+    Sphere { center: Point, radius: f64 },
+    /// An axis-aligned box `2 * half_extents` across.
+    Cuboid { center: Point, half_extents: Vec3 },
+    /// A torus lying flat in the xz plane.
+    Torus { center: Point, major_radius: f64, minor_radius: f64 },
+    /// A capped cylinder standing along y.
+    Cylinder { center: Point, radius: f64, half_height: f64 },
+    /// The Mandelbulb fractal of the given `power`, scaled to fit in a sphere
+    /// of about `radius`. Its distance is only an estimate, but a good enough
+    /// one to march with.
+    Mandelbulb { center: Point, radius: f64, power: f64, iterations: usize },
+    /// Pushes the surface out by `radius`, rounding off edges and corners.
+    Round(Box<SdfShape>, f64),
+    Union(Box<SdfShape>, Box<SdfShape>),
+    Intersection(Box<SdfShape>, Box<SdfShape>),
+    Difference(Box<SdfShape>, Box<SdfShape>),
+    /// Like the plain operators, but blending the two shapes together over a
+    /// distance of about `k` where they meet.
+    SmoothUnion(Box<SdfShape>, Box<SdfShape>, f64),
+    SmoothIntersection(Box<SdfShape>, Box<SdfShape>, f64),
+    SmoothDifference(Box<SdfShape>, Box<SdfShape>, f64),
+}
+
+impl SdfShape {
+    pub fn distance(&self, p: &Point) -> f64 {
+        match self {
+            Self::Sphere { center, radius } => (*p - *center).length() - radius,
+            Self::Cuboid { center, half_extents } => {
+                let q = *p - *center;
+                let q = Vec3::new(q.x.abs(), q.y.abs(), q.z.abs()) - *half_extents;
+                q.max(&Vec3::default()).length() + q.max_component().min(0.0)
+            }
+            Self::Torus { center, major_radius, minor_radius } => {
+                let q = *p - *center;
+                let ring = (q.x * q.x + q.z * q.z).sqrt() - major_radius;
+                (ring * ring + q.y * q.y).sqrt() - minor_radius
+            }
+            Self::Cylinder { center, radius, half_height } => {
+                let q = *p - *center;
+                let dx = (q.x * q.x + q.z * q.z).sqrt() - radius;
+                let dy = q.y.abs() - half_height;
+                dx.max(dy).min(0.0) + (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt()
+            }
+            Self::Mandelbulb { center, radius, power, iterations } => {
+                mandelbulb((*p - *center) / *radius, *power, *iterations) * radius
+            }
+            Self::Round(shape, radius) => shape.distance(p) - radius,
+            Self::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Self::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Self::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Self::SmoothUnion(a, b, k) => smooth_min(a.distance(p), b.distance(p), *k),
+            Self::SmoothIntersection(a, b, k) => -smooth_min(-a.distance(p), -b.distance(p), *k),
+            Self::SmoothDifference(a, b, k) => -smooth_min(-a.distance(p), b.distance(p), *k),
+        }
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        let around = |center: &Point, e: Vec3| Aabb::new(*center - e, *center + e);
+        let grown = |b: Aabb, by: f64| Aabb::new(b.min - Vec3::new(by, by, by), b.max + Vec3::new(by, by, by));
+        match self {
+            Self::Sphere { center, radius } => around(center, Vec3::new(*radius, *radius, *radius)),
+            Self::Cuboid { center, half_extents } => around(center, *half_extents),
+            Self::Torus { center, major_radius, minor_radius } => {
+                let r = major_radius + minor_radius;
+                around(center, Vec3::new(r, *minor_radius, r))
+            }
+            Self::Cylinder { center, radius, half_height } => around(center, Vec3::new(*radius, *half_height, *radius)),
+            // The bulb stays within about 1.2 of its center.
+            Self::Mandelbulb { center, radius, .. } => {
+                let r = 1.2 * radius;
+                around(center, Vec3::new(r, r, r))
+            }
+            Self::Round(shape, radius) => grown(shape.bounding_box(), *radius),
+            Self::Union(a, b) => a.bounding_box().surrounding(&b.bounding_box()),
+            Self::Intersection(a, b) | Self::SmoothIntersection(a, b, _) => {
+                let (a, b) = (a.bounding_box(), b.bounding_box());
+                Aabb::new(a.min.max(&b.min), a.max.min(&b.max))
+            }
+            Self::Difference(a, _) | Self::SmoothDifference(a, _, _) => a.bounding_box(),
+            // Blending bulges out a little, by at most k / 4.
+            Self::SmoothUnion(a, b, k) => grown(a.bounding_box().surrounding(&b.bounding_box()), k / 4.0),
+        }
+    }
+}
+
+/// Polynomial smooth minimum: equal to `a.min(b)` when the two are more than
+/// `k` apart, and pulled below it where they are close.
+#[inline]
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 { return a.min(b) }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k / 4.0
+}
+
+/// Distance estimate for the Mandelbulb about the origin, from the running
+/// derivative of the iteration.
+fn mandelbulb(p: Vec3, power: f64, iterations: usize) -> f64 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > 2.0 || r == 0.0 { break }
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + p;
+        r = z.length();
+    }
+    if r == 0.0 { return 0.0 }
+    0.5 * r.ln() * r / dr
+}
+
+/// Closer than this to the surface counts as on it.
+const EPSILON: f64 = 1.0e-4;
+const MAX_STEPS: usize = 512;
+
+/// An `SdfShape` rendered by sphere tracing: the ray repeatedly steps forward
+/// by the distance to the nearest surface, which can never overshoot it, until
+/// that distance is next to nothing. Texture coordinates come from the
+/// direction of the normal, as on a sphere.
+#[derive(Clone, Debug)]
+pub struct Sdf<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub shape: SdfShape,
+    pub material: Mat,
+    bbox: Aabb,
+}
+
+impl<Mat> Sdf<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(shape: SdfShape, material: Mat) -> Self {
+        let bbox = shape.bounding_box();
+        let pad = Vec3::new(10.0 * EPSILON, 10.0 * EPSILON, 10.0 * EPSILON);
+        Sdf { shape, material, bbox: Aabb::new(bbox.min - pad, bbox.max + pad) }
+    }
+
+    /// The normalized gradient of the distance, by central differences.
+    pub fn normal(&self, p: &Point) -> Vec3 {
+        let h = EPSILON;
+        let d = |offset: Vec3| self.shape.distance(&(*p + offset)) - self.shape.distance(&(*p - offset));
+        Vec3::new(d(Vec3::new(h, 0.0, 0.0)), d(Vec3::new(0.0, h, 0.0)), d(Vec3::new(0.0, 0.0, h))).unit_vector()
+    }
+}
+
+impl<Mat> Hit<Mat> for Sdf<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let (t0, t1) = match self.bbox.clip(ray, t_min, t_max) {
+            Some(range) => range,
+            None => return false,
+        };
+        let scale = ray.dir.length();
+
+        // March on whichever side of the surface the ray starts on, so rays
+        // refracted into the shape find their way out again. A ray leaving the
+        // surface it starts on has to get clear of it before anything counts.
+        let d0 = self.shape.distance(&ray.at(t0));
+        let mut clear = d0.abs() >= EPSILON;
+        let side = if clear { d0.signum() } else { self.normal(&ray.at(t0)).dot(&ray.dir).signum() };
+
+        let mut t = t0;
+        let mut converged = false;
+        for _ in 0..MAX_STEPS {
+            if t > t1 { return false }
+            let d = side * self.shape.distance(&ray.at(t));
+            if !clear {
+                clear = d >= EPSILON;
+                t += d.max(EPSILON) / scale;
+                continue
+            }
+            if d < EPSILON { converged = true; break }
+            t += d / scale;
+        }
+        // A ray grazing the surface can use up every step without reaching
+        // it; that is a miss, not a hit wherever the march gave up.
+        if !converged { return false }
+
+        let p = ray.at(t);
+        hit_record.t = t;
+        hit_record.p = p;
+        let outward_normal = self.normal(&p);
+        hit_record.set_face_normal(ray, &outward_normal);
+        // Texture coordinates go by which way the surface faces, as on a unit
+        // sphere, there being no parameterization of the shape to follow.
+        let (dpdu, dpdv) = sphere_derivatives(&outward_normal);
+        hit_record.set_tangents(&dpdu, &dpdv);
+        (hit_record.u, hit_record.v) = sphere_uv(&outward_normal);
+        hit_record.material = Some(&self.material);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::MatKind;
+
+    fn slab() -> Sdf<MatKind> {
+        let shape = SdfShape::Cuboid { center: Point::ORIGIN, half_extents: Vec3::new(10.0, 1.0, 10.0) };
+        Sdf::new(shape, MatKind::default())
+    }
+
+    #[test]
+    fn hits_converge_on_the_surface() {
+        let ray = Ray::new(Point::new(0.3, 5.0, 0.2), Vec3::new(0.0, -2.0, 0.0));
+        let slab = slab();
+        let mut rec = HitRecord::empty();
+        assert!(slab.hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.t - 2.0).abs() < EPSILON, "t = {}", rec.t);
+        assert!((rec.normal - Vec3::Y_HAT).near_zero());
+        assert_eq!(rec.front_face, Some(true));
+    }
+
+    #[test]
+    fn grazing_rays_that_run_out_of_steps_miss() {
+        // Just too high to count as touching the top, so every step is tiny
+        // and the march gives up long before it's past the slab.
+        let ray = Ray::new(Point::new(-10.5, 1.0 + 2.0 * EPSILON, 0.0), Vec3::X_HAT);
+        assert!(!slab().hit(&ray, 0.0, f64::INFINITY, &mut HitRecord::empty()));
+    }
+}
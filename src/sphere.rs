@@ -1,13 +1,16 @@
+use crate::aabb::Aabb;
 use crate::hit::{HitRecord, Hit};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::render::Point;
+use crate::render::{Point, PI};
 use crate::vec::Vec3;
 
-#[derive(Clone, Copy, Debug)]
+use rand::Rng;
+
+#[derive(Clone, Debug)]
 pub struct Sphere<Mat> 
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
     pub center: Point,
     pub r: f64,
@@ -16,47 +19,126 @@ where
 
 impl<Mat> Sphere<Mat>
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
     pub fn new(center: Point, r: f64, material: Mat) -> Self {
         Sphere { center, r, material}
     }
+
+    /// Cosine of the half angle of the cone the sphere fills as seen from
+    /// `origin`, or `None` from inside the sphere where it fills everything.
+    fn cos_theta_max(&self, origin: Point) -> Option<f64> {
+        let dist_sq = (self.center - origin).length_squared();
+        let r_sq = self.r * self.r;
+        if dist_sq <= r_sq { return None }
+        Some((1.0 - r_sq / dist_sq).sqrt())
+    }
+
+    /// Picks a direction from `origin` uniformly over the solid angle the sphere
+    /// subtends, so every direction sampled hits it. This is how small spherical
+    /// lights get sampled directly instead of waiting for a bounce to find them.
+    pub fn sample_direction<R: Rng + ?Sized>(&self, origin: Point, rng: &mut R) -> Option<Vec3> {
+        let cos_theta_max = self.cos_theta_max(origin)?;
+        let (w, u, v) = (self.center - origin).coordinate_system_from();
+
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let cos_theta = 1.0 + r2 * (cos_theta_max - 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * r1;
+
+        Some(u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta)
+    }
+
+    /// Solid angle density of `sample_direction` for `dir`: constant inside the
+    /// cone and zero outside it.
+    pub fn pdf_direction(&self, origin: Point, dir: &Vec3) -> f64 {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return 0.0,
+        };
+        if dir.unit_vector().dot(&(self.center - origin).unit_vector()) < cos_theta_max { return 0.0 }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
 }
 
 impl<Mat> Hit<Mat> for Sphere<Mat>
 where
-    Mat: Material + Copy + Default
+    Mat: Material + Clone + Default
 {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<Mat>) -> bool {
-        let oc: Vec3 = ray.origin - self.center;
-        let a = ray.dir.length_squared();
-        let half_b = oc.dot(&ray.dir);
-        let c = oc.length_squared() - self.r * self.r;
-
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 { return false }
-        let sqrtd = discriminant.sqrt();
-
-        // Find the nearest root that lies in the acceptable range
-        let mut root = (-half_b - sqrtd) / a; // this is the quadratic formula
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        hit_sphere(self.center, self.r, &self.material, ray, t_min, t_max, hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.r.abs(), self.r.abs(), self.r.abs());
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+/// Intersects `ray` with the sphere of radius `r` about `center`. Shared by
+/// `Sphere` and `MovingSphere`, which only differ in where the center is.
+pub(crate) fn hit_sphere<'a, Mat>(center: Point,
+                                  r: f64,
+                                  material: &'a Mat,
+                                  ray: &Ray,
+                                  t_min: f64,
+                                  t_max: f64,
+                                  hit_record: &mut HitRecord<'a, Mat>) -> bool
+where
+    Mat: Material + Clone + Default
+{
+    let oc: Vec3 = ray.origin - center;
+    let a = ray.dir.length_squared();
+    let half_b = oc.dot(&ray.dir);
+    let c = oc.length_squared() - r * r;
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 { return false }
+    let sqrtd = discriminant.sqrt();
+
+    // Find the nearest root that lies in the acceptable range
+    let mut root = (-half_b - sqrtd) / a; // this is the quadratic formula
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrtd) / a;
         if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return false
-            }
+            return false
         }
-
-        let t = root;
-        let p = ray.at(t);
-        let normal = ((p - self.center) / self.r).unit_vector(); 
-        
-        hit_record.p = p;
-        hit_record.normal = normal;
-        hit_record.t = t;
-
-        let outward_normal: Vec3 = (hit_record.p - self.center) / self.r;
-        hit_record.set_face_normal(ray, &outward_normal);
-        hit_record.material = self.material;
-        true
     }
+
+    let t = root;
+    let p = ray.at(t);
+    let normal = ((p - center) / r).unit_vector(); 
+    
+    hit_record.p = p;
+    hit_record.normal = normal;
+    hit_record.t = t;
+
+    let outward_normal: Vec3 = (hit_record.p - center) / r;
+    hit_record.set_face_normal(ray, &outward_normal);
+    let q = (p - center) / r.abs();
+    let (dpdu, dpdv) = sphere_derivatives(&q);
+    hit_record.set_tangents(&(dpdu * r.abs()), &(dpdv * r.abs()));
+    (hit_record.u, hit_record.v) = sphere_uv(&q);
+    hit_record.material = Some(material);
+    true
+}
+
+/// How a point on the unit sphere moves with the `u` and `v` of `sphere_uv`:
+/// around the y axis and from pole to pole. Both vanish at the poles.
+#[inline]
+pub fn sphere_derivatives(p: &Point) -> (Vec3, Vec3) {
+    let ring = (p.x * p.x + p.z * p.z).sqrt();
+    let dpdu = Vec3::new(p.z, 0.0, -p.x) * (2.0 * PI);
+    if ring == 0.0 { return (dpdu, Vec3::default()) }
+    (dpdu, Vec3::new(-p.x * p.y / ring, ring, -p.y * p.z / ring) * PI)
+}
+
+/// Texture coordinates of a point on the unit sphere: `u` goes once around the
+/// y axis starting from -x, `v` runs from the bottom pole (0) to the top (1).
+#[inline]
+pub fn sphere_uv(p: &Point) -> (f64, f64) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
 }
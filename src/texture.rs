@@ -0,0 +1,303 @@
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Result};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::hdr::{load_hdr, HdrImage};
+use crate::render::{Color, Point, RenderRng};
+use crate::vec::Vec3;
+
+use rand::prelude::*;
+
+/// Where a material gets its color from at a hit point. Everything but image
+/// textures is a few numbers, so cloning a material stays cheap.
+#[derive(Clone, Debug)]
+pub enum Texture {
+    Solid(Color),
+    /// Alternating cubes of side `scale` filling space, so the pattern doesn't
+    /// depend on how the surface is parameterized.
+    Checker { even: Color, odd: Color, scale: f64 },
+    Image(Arc<ImageTexture>),
+    /// Perlin noise, remapped to [0, 1] and scaling `color`. `scale` is the
+    /// frequency: larger values give finer detail.
+    Noise { color: Color, scale: f64 },
+    /// `depth` octaves of Perlin noise summed, for a marbled, cloudy look.
+    Turbulence { color: Color, scale: f64, depth: usize },
+}
+
+impl Texture {
+    pub fn value(&self, u: f64, v: f64, p: &Point) -> Color {
+        match self {
+            Self::Solid(color) => *color,
+            Self::Checker { even, odd, scale } => {
+                let cell = |x: f64| (x / scale).floor() as i64;
+                if (cell(p.x) + cell(p.y) + cell(p.z)).rem_euclid(2) == 0 { *even } else { *odd }
+            }
+            Self::Image(image) => image.value(u, v),
+            Self::Noise { color, scale } => *color * 0.5 * (1.0 + perlin().noise(&(*p * *scale))),
+            Self::Turbulence { color, scale, depth } => *color * perlin().turbulence(&(*p * *scale), *depth),
+        }
+    }
+}
+
+impl Default for Texture {
+    fn default() -> Self {
+        Texture::Solid(Color::default())
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Self {
+        Texture::Solid(color)
+    }
+}
+
+/// An image wrapped around a surface by its (u, v) coordinates, with `v = 0` at
+/// the bottom of the image. PNG and JPEG files are taken to be gamma 2 encoded,
+/// the inverse of what the renderer writes, and brought back to linear; `.hdr`
+/// files are linear already. Images holding data rather than colors, such as
+/// normal maps, should be loaded with `load_raw` instead.
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    image: HdrImage,
+}
+
+impl ImageTexture {
+    /// An image already in memory, taken to be linear.
+    pub fn new(image: HdrImage) -> Self {
+        ImageTexture { image }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with(path, true)
+    }
+
+    /// Loads PNG and JPEG files as stored, without undoing any gamma.
+    pub fn load_raw(path: &Path) -> Result<Self> {
+        Self::load_with(path, false)
+    }
+
+    fn load_with(path: &Path, gamma: bool) -> Result<Self> {
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase());
+        let image = match extension.as_deref() {
+            Some("png") => load_png(path, gamma)?,
+            Some("jpg") | Some("jpeg") => load_jpeg(path, gamma)?,
+            Some("hdr") => load_hdr(path)?,
+            _ => return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("can't tell the image format of {}: use a .png, .jpg or .hdr file", path.display()),
+            )),
+        };
+        Ok(ImageTexture { image })
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height
+    }
+
+    /// Nearest texel at (u, v), clamping outside the unit square.
+    pub fn value(&self, u: f64, v: f64) -> Color {
+        let (w, h) = (self.image.width, self.image.height);
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let x = ((u * w as f64) as usize).min(w - 1);
+        let y = ((v * h as f64) as usize).min(h - 1);
+        self.image.pixels[y * w + x]
+    }
+}
+
+#[inline]
+fn decode_channel(value: u8, gamma: bool) -> f64 {
+    let c = value as f64 / 255.0;
+    if gamma { c * c } else { c }
+}
+
+/// Turns 8-bit pixel data with `channels` channels per pixel into linear color,
+/// or into plain numbers from 0 to 1 without `gamma`. One or two channels are
+/// gray (plus alpha); three or four are RGB (plus alpha).
+fn decode_pixels(bytes: &[u8], channels: usize, gamma: bool) -> Vec<Color> {
+    bytes.chunks_exact(channels).map(|px| match channels {
+        1 | 2 => {
+            let l = decode_channel(px[0], gamma);
+            Color::new(l, l, l)
+        }
+        _ => Color::new(decode_channel(px[0], gamma), decode_channel(px[1], gamma), decode_channel(px[2], gamma)),
+    }).collect()
+}
+
+fn load_png(path: &Path, gamma: bool) -> Result<HdrImage> {
+    let invalid = |e: png::DecodingError| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e));
+    let file = File::open(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    // Palettes are expanded and 16-bit channels cut to 8, so only the channel
+    // count is left to deal with.
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut bytes = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut bytes).map_err(invalid)?;
+    bytes.truncate(info.buffer_size());
+
+    let channels = info.color_type.samples();
+    let pixels = decode_pixels(&bytes, channels, gamma);
+    Ok(HdrImage { width: info.width as usize, height: info.height as usize, pixels })
+}
+
+fn load_jpeg(path: &Path, gamma: bool) -> Result<HdrImage> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+    let file = File::open(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+    let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(file));
+    let bytes = decoder.decode().map_err(|e| invalid(e.to_string()))?;
+    let info = decoder.info().ok_or_else(|| invalid("missing image header".to_string()))?;
+    let channels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => 1,
+        jpeg_decoder::PixelFormat::RGB24 => 3,
+        format => return Err(invalid(format!("unsupported pixel format {:?}", format))),
+    };
+
+    let pixels = decode_pixels(&bytes, channels, gamma);
+    Ok(HdrImage { width: info.width as usize, height: info.height as usize, pixels })
+}
+
+const POINT_COUNT: usize = 256;
+
+/// Ken Perlin's gradient noise, as in The Next Week: random unit vectors at the
+/// lattice points, picked through three shuffled index tables.
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+/// Every noise texture shares one table, built from a fixed seed so renders
+/// stay reproducible.
+fn perlin() -> &'static Perlin {
+    static PERLIN: OnceLock<Perlin> = OnceLock::new();
+    PERLIN.get_or_init(|| Perlin::new(&mut RenderRng::seed_from_u64(0x5eed)))
+}
+
+impl Perlin {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let gradients = (0..POINT_COUNT).map(|_| Vec3::random(-1.0, 1.0, rng).unit_vector()).collect();
+        let mut perm = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(rng);
+            p
+        };
+        let (perm_x, perm_y, perm_z) = (perm(), perm(), perm());
+        Perlin { gradients, perm_x, perm_y, perm_z }
+    }
+
+    /// Noise in roughly [-1, 1].
+    pub fn noise(&self, p: &Point) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        let mut c = [[[Vec3::default(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        // Trilinear interpolation of the gradient dot products, with Hermite
+        // smoothing to hide the lattice.
+        let (uu, vv, ww) = (u * u * (3.0 - 2.0 * u), v * v * (3.0 - 2.0 * v), w * w * (3.0 - 2.0 * w));
+        let mut accum = 0.0;
+        for (di, plane) in c.iter().enumerate() {
+            for (dj, row) in plane.iter().enumerate() {
+                for (dk, gradient) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// Sum of `depth` octaves, each at twice the frequency and half the weight
+    /// of the last.
+    pub fn turbulence(&self, p: &Point, depth: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_alternates_across_cell_boundaries() {
+        let (even, odd) = (Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0));
+        let checker = Texture::Checker { even, odd, scale: 0.5 };
+        let at = |x: f64, y: f64, z: f64| checker.value(0.0, 0.0, &Point::new(x, y, z));
+        assert_eq!(at(0.25, 0.25, 0.25), even);
+        for (x, y, z) in [(0.75, 0.25, 0.25), (0.25, 0.75, 0.25), (0.25, 0.25, 0.75)] {
+            assert_eq!(at(x, y, z), odd);
+        }
+        // Cells are floored, so crossing zero flips the color like any other
+        // boundary instead of doubling the cell around the origin.
+        assert_eq!(at(-0.25, 0.25, 0.25), odd);
+        assert_eq!(at(-0.75, 0.25, 0.25), even);
+        assert_eq!(at(0.49, 0.25, 0.25), even);
+        assert_eq!(at(0.51, 0.25, 0.25), odd);
+    }
+
+    #[test]
+    fn image_corners_read_the_corner_texels() {
+        // Texel colors are their column and row, top row first.
+        let (width, height) = (3, 2);
+        let pixels = (0..width * height).map(|i| Color::new((i % width) as f64, (i / width) as f64, 0.0));
+        let texture = ImageTexture { image: HdrImage { width, height, pixels: pixels.collect() } };
+        assert_eq!(texture.value(0.0, 0.0), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(texture.value(1.0, 1.0), Color::new(2.0, 0.0, 0.0));
+        assert_eq!(texture.value(0.0, 1.0), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(texture.value(1.0, 0.0), Color::new(2.0, 1.0, 0.0));
+        assert_eq!(texture.value(0.5, 0.25), Color::new(1.0, 1.0, 0.0));
+        // Outside the unit square clamps to the edge.
+        assert_eq!(texture.value(-0.5, 1.5), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(texture.value(1.5, -0.5), Color::new(2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn noise_stays_in_range_and_vanishes_on_the_lattice() {
+        let color = Color::new(1.0, 0.5, 0.25);
+        let noise = Texture::Noise { color, scale: 4.0 };
+        let turbulence = Texture::Turbulence { color, scale: 4.0, depth: 7 };
+        let mut rng = RenderRng::seed_from_u64(0x5eed);
+        for _ in 0..1000 {
+            let p = Vec3::random(-10.0, 10.0, &mut rng);
+            let n = noise.value(0.0, 0.0, &p);
+            assert!((0.0..=1.0).contains(&n.x) && (n.y - 0.5 * n.x).abs() < 1.0e-12, "{:?} at {:?}", n, p);
+            assert!(turbulence.value(0.0, 0.0, &p).x >= 0.0);
+        }
+        // At lattice points every gradient is dotted with a zero offset.
+        assert_eq!(perlin().noise(&Point::new(3.0, -2.0, 5.0)), 0.0);
+        assert_eq!(noise.value(0.0, 0.0, &Point::new(0.75, -0.5, 1.25)), color * 0.5);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::aabb::Aabb;
+use crate::frame::Frame;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::plane::{disk_box, polar_angle};
+use crate::ray::Ray;
+use crate::render::{Point, PI};
+use crate::vec::Vec3;
+
+/// A ring: the surface swept by a circle of `minor_radius` whose center runs
+/// around a circle of `major_radius` about `center`, in the plane facing
+/// `axis`. `u` goes around the axis and `v` around the tube.
+#[derive(Clone, Debug)]
+pub struct Torus<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    frame: Frame,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Mat,
+}
+
+impl<Mat> Torus<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(center: Point, axis: Vec3, major_radius: f64, minor_radius: f64, material: Mat) -> Self {
+        Torus { frame: Frame::new(center, axis), major_radius, minor_radius, material }
+    }
+}
+
+impl<Mat> Hit<Mat> for Torus<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let (big_r, r) = (self.major_radius, self.minor_radius);
+        let scale = ray.dir.length();
+        let d = self.frame.vector_to_local(&ray.dir) / scale;
+        let mut o = self.frame.to_local(&ray.origin);
+
+        // The quartic loses precision quickly with distance, so start the ray
+        // where it enters the torus' bounding sphere (`s0` along it).
+        let bound = big_r + r;
+        let od = o.dot(&d);
+        let disc = od * od - (o.length_squared() - bound * bound);
+        if disc < 0.0 { return false }
+        let s0 = (-od - disc.sqrt()).max(0.0);
+        o += d * s0;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = o + s d, |d| = 1.
+        let od = o.dot(&d);
+        let e = o.length_squared() + big_r * big_r - r * r;
+        let four_r2 = 4.0 * big_r * big_r;
+        let coeffs = [
+            1.0,
+            4.0 * od,
+            4.0 * od * od + 2.0 * e - four_r2 * (d.x * d.x + d.y * d.y),
+            4.0 * od * e - 2.0 * four_r2 * (o.x * d.x + o.y * d.y),
+            e * e - four_r2 * (o.x * o.x + o.y * o.y),
+        ];
+
+        let t = solve_quartic(coeffs)
+            .into_iter()
+            .map(|s| (s + s0) / scale)
+            .filter(|t| (t_min..=t_max).contains(t))
+            .fold(None, |closest: Option<f64>, t| Some(closest.map_or(t, |c| c.min(t))));
+        let t = match t {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let p = self.frame.to_local(&ray.at(t));
+        let ring = (p.x * p.x + p.y * p.y).sqrt();
+        // Out from the axis toward p. A torus whose tube reaches the axis can
+        // be hit right on it, where any direction in the plane will do.
+        let radial = if ring > 0.0 { Vec3::new(p.x / ring, p.y / ring, 0.0) } else { Vec3::X_HAT };
+        // From the nearest point on the ring of tube centers out to p.
+        let center = radial * big_r;
+        let outward_normal = self.frame.vector_to_world(&(p - center)).unit_vector();
+
+        hit_record.t = t;
+        hit_record.p = ray.at(t);
+        hit_record.set_face_normal(ray, &outward_normal);
+        // `u` goes around the axis and `v` around the tube.
+        let around_axis = Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI);
+        let around_tube = (radial * -p.z + Vec3::new(0.0, 0.0, ring - big_r)) * (2.0 * PI);
+        hit_record.set_tangents(&self.frame.vector_to_world(&around_axis), &self.frame.vector_to_world(&around_tube));
+        (hit_record.u, hit_record.v) = (polar_angle(p.x, p.y), polar_angle(ring - big_r, p.z));
+        hit_record.material = Some(&self.material);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let ring = disk_box(self.frame.origin, &self.frame.w, self.major_radius);
+        let r = Vec3::new(self.minor_radius, self.minor_radius, self.minor_radius);
+        Some(Aabb::new(ring.min - r, ring.max + r))
+    }
+}
+
+/// Real roots of the quartic `c[0] x^4 + c[1] x^3 + c[2] x^2 + c[3] x + c[4]`,
+/// by Ferrari's method, each polished with a few Newton steps.
+fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    let (a, b, cc, d) = (c[1] / c[0], c[2] / c[0], c[3] / c[0], c[4] / c[0]);
+
+    // Depress to y^4 + p y^2 + q y + r with x = y - a/4.
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut roots = Vec::with_capacity(4);
+    let mut push_quadratic = |b: f64, c: f64| {
+        let disc = b * b - 4.0 * c;
+        if disc >= 0.0 {
+            let sq = disc.sqrt();
+            roots.push((-b - sq) / 2.0);
+            roots.push((-b + sq) / 2.0);
+        }
+    };
+
+    if q.abs() < 1.0e-12 {
+        // Biquadratic: a quadratic in y^2.
+        let disc = p * p - 4.0 * r;
+        if disc < 0.0 { return Vec::new() }
+        for z in [(-p - disc.sqrt()) / 2.0, (-p + disc.sqrt()) / 2.0] {
+            if z >= 0.0 {
+                push_quadratic(0.0, -z);
+            }
+        }
+    } else {
+        // A positive root of the resolvent cubic turns the depressed quartic
+        // into a product of two quadratics.
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m <= 0.0 { return Vec::new() }
+        let s = (2.0 * m).sqrt();
+        push_quadratic(-s, p / 2.0 + m + q / (2.0 * s));
+        push_quadratic(s, p / 2.0 + m - q / (2.0 * s));
+    }
+
+    roots
+        .into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..3 {
+                let f = (((c[0] * x + c[1]) * x + c[2]) * x + c[3]) * x + c[4];
+                let df = ((4.0 * c[0] * x + 3.0 * c[1]) * x + 2.0 * c[2]) * x + c[3];
+                if df == 0.0 { break }
+                x -= f / df;
+            }
+            x
+        })
+        .collect()
+}
+
+/// Largest real root of `x^3 + a x^2 + b x + c`.
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let q3 = q * q * q;
+    if r * r < q3 {
+        // Three real roots; in the trigonometric form this one is the largest.
+        let theta = (r / q3.sqrt()).clamp(-1.0, 1.0).acos();
+        -2.0 * q.sqrt() * ((theta + 2.0 * PI) / 3.0).cos() - a / 3.0
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q3).sqrt()).cbrt();
+        let big_b = if big_a != 0.0 { q / big_a } else { 0.0 };
+        big_a + big_b - a / 3.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::MatKind;
+
+    const EPSILON: f64 = 1.0e-9;
+
+    fn sorted_roots(c: [f64; 5]) -> Vec<f64> {
+        let mut roots = solve_quartic(c);
+        roots.sort_by(f64::total_cmp);
+        roots
+    }
+
+    fn assert_roots(c: [f64; 5], expected: &[f64]) {
+        let roots = sorted_roots(c);
+        assert_eq!(roots.len(), expected.len(), "{:?} for {:?}", roots, c);
+        for (root, want) in roots.iter().zip(expected) {
+            assert!((root - want).abs() < EPSILON, "{:?} for {:?}", roots, c);
+        }
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots([1.0, -10.0, 35.0, -50.0, 24.0], &[1.0, 2.0, 3.0, 4.0]);
+        // The same, not monic.
+        assert_roots([2.0, -20.0, 70.0, -100.0, 48.0], &[1.0, 2.0, 3.0, 4.0]);
+        // Biquadratic: (x^2 - 1)(x^2 - 4)
+        assert_roots([1.0, 0.0, -5.0, 0.0, 4.0], &[-2.0, -1.0, 1.0, 2.0]);
+        // (x^2 + 1)(x - 1)(x - 2) has just two real roots.
+        assert_roots([1.0, -3.0, 3.0, -3.0, 2.0], &[1.0, 2.0]);
+        // x^4 + 1 and (x^2 + 1)(x^2 + 4) have none.
+        assert_roots([1.0, 0.0, 0.0, 0.0, 1.0], &[]);
+        assert_roots([1.0, 0.0, 5.0, 0.0, 4.0], &[]);
+    }
+
+    #[test]
+    fn hits_the_near_side_of_the_tube() {
+        let torus = Torus::new(Point::ORIGIN, Vec3::Z_HAT, 2.0, 0.5, MatKind::default());
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let mut rec = HitRecord::empty();
+        assert!(torus.hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.t - 1.25).abs() < EPSILON, "t = {}", rec.t);
+        assert!((rec.normal + Vec3::X_HAT).near_zero());
+        // From inside the hole the inner side of the tube is next.
+        let mut rec = HitRecord::empty();
+        assert!(torus.hit(&ray, 2.0, f64::INFINITY, &mut rec));
+        assert!((rec.p - Point::new(1.5, 0.0, 0.0)).near_zero(), "p = {:?}", rec.p);
+        assert!((rec.normal + Vec3::X_HAT).near_zero());
+        // Straight down the hole misses.
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), -Vec3::Z_HAT);
+        assert!(!torus.hit(&ray, 0.0, f64::INFINITY, &mut HitRecord::empty()));
+    }
+
+    #[test]
+    fn hits_on_the_axis_are_finite() {
+        // The tube reaches past the axis, so the surface crosses it at
+        // z = +-sqrt(r^2 - R^2) = +-1.
+        let torus = Torus::new(Point::ORIGIN, Vec3::Z_HAT, 1.0, 2.0_f64.sqrt(), MatKind::default());
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), -Vec3::Z_HAT);
+        let mut rec = HitRecord::empty();
+        assert!(torus.hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.t - 4.0).abs() < 1.0e-6, "t = {}", rec.t);
+        assert!(rec.normal.x.is_finite() && rec.normal.y.is_finite() && rec.normal.z.is_finite());
+        assert!(rec.u.is_finite() && rec.v.is_finite());
+    }
+}
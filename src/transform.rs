@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HitRecord, Hittable};
+use crate::material::Material;
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::render::Point;
+
+/// An instance of `object` placed in the world by an affine `matrix`. Rays
+/// are taken into the object's own space to be intersected and the hit is
+/// brought back out, so the object itself never changes and any number of
+/// transforms can share one through the `Arc`.
+#[derive(Clone, Debug)]
+pub struct Transform<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub object: Arc<Hittable<Mat>>,
+    matrix: Matrix4,
+    inverse: Matrix4,
+    /// Normals are covectors and go through the inverse transpose, which keeps
+    /// them perpendicular to the surface under non-uniform scaling.
+    normal_matrix: Matrix4,
+    bbox: Option<Aabb>,
+}
+
+impl<Mat> Transform<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    /// `None` if `matrix` can't be inverted.
+    pub fn new(object: Arc<Hittable<Mat>>, matrix: Matrix4) -> Option<Self> {
+        let inverse = matrix.inverse()?;
+        let bbox = object.bounding_box().map(|b| {
+            let corners = (0..8).map(|i| {
+                let pick = |bit: usize, min: f64, max: f64| if i & bit == 0 { min } else { max };
+                Point::new(pick(1, b.min.x, b.max.x), pick(2, b.min.y, b.max.y), pick(4, b.min.z, b.max.z))
+            });
+            corners.fold(Aabb::EMPTY, |acc, p| acc.grow(&matrix.transform_point(&p)))
+        });
+        Some(Transform { object, matrix, inverse, normal_matrix: inverse.transpose(), bbox })
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    /// `ray` in the object's space. The direction isn't renormalized, so `t`
+    /// means the same on both sides.
+    fn to_local(&self, ray: &Ray) -> Ray {
+        Ray::with_time(self.inverse.transform_point(&ray.origin), self.inverse.transform_vector(&ray.dir), ray.time)
+    }
+
+    /// Brings a hit on the object back out into the world. Which side was hit
+    /// doesn't change: dir . (M^-T n) is (M^-1 dir) . n, the same product the
+    /// object worked out. Tangents and derivatives go along with the surface,
+    /// so they are transformed like any other direction, and the tangents then
+    /// squared up again. The frame is carried over as it was rather than built
+    /// afresh from the derivatives, which would undo a `Csg` turning it over.
+    fn to_world(&self, rec: &mut HitRecord<Mat>) {
+        rec.p = self.matrix.transform_point(&rec.p);
+        rec.normal = self.normal_matrix.transform_vector(&rec.normal).unit_vector();
+        let (dpdu, dpdv) = (self.matrix.transform_vector(&rec.dpdu), self.matrix.transform_vector(&rec.dpdv));
+        let tangent = self.matrix.transform_vector(&rec.tangent);
+        let bitangent = self.matrix.transform_vector(&rec.bitangent);
+        rec.set_tangents(&tangent, &bitangent);
+        (rec.dpdu, rec.dpdv) = (dpdu, dpdv);
+    }
+}
+
+impl<Mat> Hit<Mat> for Transform<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a, Mat>) -> bool {
+        if !self.object.hit(&self.to_local(ray), t_min, t_max, rec) { return false }
+        self.to_world(rec);
+        true
+    }
+
+    fn hits<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'a, Mat>> {
+        let mut hits = self.object.hits(&self.to_local(ray), t_min, t_max);
+        hits.iter_mut().for_each(|rec| self.to_world(rec));
+        hits
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, MatKind};
+    use crate::render::Color;
+    use crate::sphere::Sphere;
+    use crate::vec::Vec3;
+
+    const EPSILON: f64 = 1.0e-9;
+
+    /// The unit sphere stretched to twice its size along x.
+    fn ellipsoid() -> Transform<MatKind> {
+        let material = MatKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Hittable::Sphere(Sphere::new(Point::ORIGIN, 1.0, material));
+        Transform::new(Arc::new(sphere), Matrix4::scaling(Vec3::new(2.0, 1.0, 1.0))).unwrap()
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scaling() {
+        // On x^2/4 + y^2 + z^2 = 1 the normal goes along the gradient,
+        // (x/4, y, z), not along the stretched sphere normal (x/2, y, z).
+        let p = Point::new(2.0_f64.sqrt(), 0.5_f64.sqrt(), 0.0);
+        let normal = Vec3::new(p.x / 4.0, p.y, p.z).unit_vector();
+        // Not unit length, so a t that got renormalized along the way shows.
+        let ray = Ray::new(p + normal * 5.0, -normal * 2.0);
+
+        let transform = ellipsoid();
+        let mut rec = HitRecord::empty();
+        assert!(transform.hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.t - 2.5).abs() < EPSILON, "t = {}", rec.t);
+        assert!((rec.p - p).near_zero());
+        assert!((ray.at(rec.t) - rec.p).near_zero());
+        assert!((rec.normal - normal).near_zero(), "normal = {:?}", rec.normal);
+        assert_eq!(rec.front_face, Some(true));
+        assert!(rec.tangent.dot(&rec.normal).abs() < EPSILON && rec.bitangent.dot(&rec.normal).abs() < EPSILON);
+    }
+
+    #[test]
+    fn every_hit_keeps_its_t() {
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::new(0.5, 0.0, 0.0));
+        let transform = ellipsoid();
+        let hits = transform.hits(&ray, 0.0, f64::INFINITY);
+        let ts: Vec<f64> = hits.iter().map(|rec| rec.t).collect();
+        assert_eq!(ts.len(), 2);
+        assert!((ts[0] - 6.0).abs() < EPSILON && (ts[1] - 14.0).abs() < EPSILON, "{:?}", ts);
+        assert!((hits[1].p - Point::new(2.0, 0.0, 0.0)).near_zero());
+        assert_eq!(hits[1].front_face, Some(false));
+    }
+
+    #[test]
+    fn singular_matrices_are_refused() {
+        let sphere = Arc::new(Hittable::Sphere(Sphere::new(Point::ORIGIN, 1.0, MatKind::default())));
+        assert!(Transform::new(sphere, Matrix4::scaling(Vec3::new(1.0, 1.0, 0.0))).is_none());
+    }
+}
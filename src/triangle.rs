@@ -0,0 +1,256 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hit};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render::Point;
+use crate::vec::Vec3;
+
+use rand::Rng;
+
+/// Boxes around triangles lying in an axis-aligned plane would otherwise have
+/// zero thickness, which the slab test handles poorly.
+pub(crate) const BOX_PADDING: f64 = 1.0e-4;
+
+/// A single triangle with optional per-vertex shading normals and texture
+/// coordinates. Vertices are wound counter-clockwise when seen from the front.
+#[derive(Clone, Debug)]
+pub struct Triangle<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub vertices: [Point; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub uvs: Option<[(f64, f64); 3]>,
+    pub material: Mat,
+}
+
+impl<Mat> Triangle<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    pub fn new(vertices: [Point; 3], normals: Option<[Vec3; 3]>, uvs: Option<[(f64, f64); 3]>, material: Mat) -> Self {
+        Triangle { vertices, normals, uvs, material }
+    }
+
+    pub fn area(&self) -> f64 {
+        let [v0, v1, v2] = self.vertices;
+        0.5 * (v1 - v0).cross(&(v2 - v0)).length()
+    }
+
+    /// Direction from `origin` to a point picked uniformly over the triangle's
+    /// area, for sampling triangle lights.
+    pub fn sample_direction<R: Rng + ?Sized>(&self, origin: Point, rng: &mut R) -> Vec3 {
+        let [v0, v1, v2] = self.vertices;
+        // Folding the unit square onto the triangle by a square root keeps the
+        // density uniform.
+        let s = rng.gen::<f64>().sqrt();
+        let b2 = s * rng.gen::<f64>();
+        interpolate(v0, v1, v2, s - b2, b2) - origin
+    }
+
+    /// Solid angle density of `sample_direction` for `dir`: the area density
+    /// converted by the squared distance over the cosine at the light.
+    pub fn pdf_direction(&self, origin: Point, dir: &Vec3) -> f64 {
+        let [v0, v1, v2] = self.vertices;
+        let (t, _, _) = match intersect(&Ray::new(origin, *dir), v0, v1, v2, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let normal = (v1 - v0).cross(&(v2 - v0)).unit_vector();
+        let distance_squared = t * t * dir.length_squared();
+        let cosine = (dir.dot(&normal) / dir.length()).abs();
+        if cosine <= 0.0 { return 0.0 }
+        distance_squared / (cosine * self.area())
+    }
+}
+
+impl<Mat> Hit<Mat> for Triangle<Mat>
+where
+    Mat: Material + Clone + Default
+{
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a, Mat>) -> bool {
+        let [v0, v1, v2] = self.vertices;
+        let (t, b1, b2) = match intersect(ray, v0, v1, v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let normals = self.normals.map(|[n0, n1, n2]| interpolate(n0, n1, n2, b1, b2));
+        set_hit_record(ray, t, v0, v1, v2, normals, self.uvs, (b1, b2), &self.material, hit_record);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [v0, v1, v2] = self.vertices;
+        Some(triangle_box(v0, v1, v2))
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection. Returns the ray parameter along
+/// with the barycentric coordinates of the hit relative to `v1` and `v2`; the
+/// weight of `v0` is whatever is left over.
+#[inline]
+pub fn intersect(ray: &Ray, v0: Point, v1: Point, v2: Point, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    const EPSILON: f64 = 1.0e-12;
+
+    let e1 = v1 - v0;
+    let e2 = v2 - v0;
+    let pvec = ray.dir.cross(&e2);
+    let det = e1.dot(&pvec);
+    // Parallel to the plane of the triangle (or a degenerate triangle).
+    if det.abs() < EPSILON { return None }
+    let inv_det = 1.0 / det;
+
+    let tvec = ray.origin - v0;
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) { return None }
+
+    let qvec = tvec.cross(&e1);
+    let b2 = ray.dir.dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 { return None }
+
+    let t = e2.dot(&qvec) * inv_det;
+    if t < t_min || t_max < t { return None }
+
+    Some((t, b1, b2))
+}
+
+#[inline]
+pub fn interpolate(a0: Vec3, a1: Vec3, a2: Vec3, b1: f64, b2: f64) -> Vec3 {
+    a0 * (1.0 - b1 - b2) + a1 * b1 + a2 * b2
+}
+
+#[inline]
+pub fn interpolate_uv(uvs: [(f64, f64); 3], b1: f64, b2: f64) -> (f64, f64) {
+    let b0 = 1.0 - b1 - b2;
+    (b0 * uvs[0].0 + b1 * uvs[1].0 + b2 * uvs[2].0,
+     b0 * uvs[0].1 + b1 * uvs[1].1 + b2 * uvs[2].1)
+}
+
+/// How the position changes with `u` and with `v` across the triangle. Faces
+/// whose texture coordinates don't span an area get its edges instead.
+#[inline]
+fn uv_derivatives(v0: Point, v1: Point, v2: Point, uvs: [(f64, f64); 3]) -> (Vec3, Vec3) {
+    let (e1, e2) = (v1 - v0, v2 - v0);
+    let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+    let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+    let det = du1 * dv2 - dv1 * du2;
+    if det.abs() < 1.0e-12 { return (e1, e2) }
+    ((e1 * dv2 - e2 * dv1) / det, (e2 * du1 - e1 * du2) / det)
+}
+
+/// Fills in the hit record shared by lone triangles and mesh faces. Which side
+/// is the front is decided by the geometric normal; an interpolated shading
+/// normal, when there is one, only bends the normal handed to the material.
+/// Without texture coordinates `(u, v)` is just the barycentric coordinates
+/// `(b1, b2)`.
+#[allow(clippy::too_many_arguments)]
+#[inline]
+pub fn set_hit_record<'a, Mat>(
+    ray: &Ray,
+    t: f64,
+    v0: Point,
+    v1: Point,
+    v2: Point,
+    shading_normal: Option<Vec3>,
+    uvs: Option<[(f64, f64); 3]>,
+    (b1, b2): (f64, f64),
+    material: &'a Mat,
+    hit_record: &mut HitRecord<'a, Mat>)
+where
+    Mat: Material + Clone + Default
+{
+    let mut outward_normal = (v1 - v0).cross(&(v2 - v0)).unit_vector();
+
+    hit_record.p = ray.at(t);
+    hit_record.t = t;
+    match shading_normal {
+        Some(n) => {
+            let n = n.unit_vector();
+            // Trust the vertex normals over the winding order if they disagree.
+            if outward_normal.dot(&n) < 0.0 { outward_normal = -outward_normal; }
+            hit_record.set_face_normal(ray, &outward_normal);
+            hit_record.normal = if hit_record.front_face.unwrap() { n } else { -n };
+        }
+        None => hit_record.set_face_normal(ray, &outward_normal),
+    }
+    let uvs = uvs.unwrap_or([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+    let (dpdu, dpdv) = uv_derivatives(v0, v1, v2, uvs);
+    hit_record.set_tangents(&dpdu, &dpdv);
+    (hit_record.u, hit_record.v) = interpolate_uv(uvs, b1, b2);
+    hit_record.material = Some(material);
+}
+
+#[inline]
+pub fn triangle_box(v0: Point, v1: Point, v2: Point) -> Aabb {
+    let pad = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+    Aabb::new(v0.min(&v1).min(&v2) - pad, v0.max(&v1).max(&v2) + pad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, MatKind};
+    use crate::render::Color;
+
+    const EPSILON: f64 = 1.0e-9;
+
+    fn triangle(uvs: Option<[(f64, f64); 3]>) -> Triangle<MatKind> {
+        let vertices = [Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0)];
+        Triangle::new(vertices, None, uvs, MatKind::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5))))
+    }
+
+    #[test]
+    fn intersect_finds_t_and_barycentrics() {
+        let (v0, v1, v2) = (Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0));
+        let ray = Ray::new(Point::new(0.25, 0.5, 2.0), Vec3::new(0.0, 0.0, -2.0));
+        let (t, b1, b2) = intersect(&ray, v0, v1, v2, 0.0, f64::INFINITY).expect("should hit");
+        assert!((t - 1.0).abs() < EPSILON);
+        assert!((b1 - 0.25).abs() < EPSILON);
+        assert!((b2 - 0.5).abs() < EPSILON);
+
+        // From behind works as well; culling is up to the caller.
+        let back = Ray::new(Point::new(0.25, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(intersect(&back, v0, v1, v2, 0.0, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn intersect_misses() {
+        let (v0, v1, v2) = (Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0));
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        // Outside each edge in turn.
+        for origin in [Point::new(-0.1, 0.5, 1.0), Point::new(0.5, -0.1, 1.0), Point::new(0.6, 0.6, 1.0)] {
+            assert!(intersect(&Ray::new(origin, down), v0, v1, v2, 0.0, f64::INFINITY).is_none());
+        }
+        // Parallel to the plane.
+        let parallel = Ray::new(Point::new(-1.0, 0.2, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(intersect(&parallel, v0, v1, v2, 0.0, f64::INFINITY).is_none());
+        // Outside the range of t.
+        let ray = Ray::new(Point::new(0.2, 0.2, 1.0), down);
+        assert!(intersect(&ray, v0, v1, v2, 0.0, 0.5).is_none());
+        assert!(intersect(&ray, v0, v1, v2, 1.5, f64::INFINITY).is_none());
+        // Pointing away.
+        assert!(intersect(&Ray::new(Point::new(0.2, 0.2, 1.0), -down), v0, v1, v2, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn hit_interpolates_uvs() {
+        let ray = Ray::new(Point::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        // Without texture coordinates (u, v) are the barycentrics.
+        let plain = triangle(None);
+        let mut rec = HitRecord::empty();
+        assert!(plain.hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.u - 0.25).abs() < EPSILON && (rec.v - 0.5).abs() < EPSILON);
+        assert_eq!(rec.front_face, Some(true));
+        assert!((rec.normal - Vec3::Z_HAT).near_zero());
+
+        let uvs = [(0.5, 0.5), (1.0, 0.5), (0.5, 1.0)];
+        let textured = triangle(Some(uvs));
+        let mut rec = HitRecord::empty();
+        assert!(textured.hit(&ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.u - 0.625).abs() < EPSILON && (rec.v - 0.75).abs() < EPSILON);
+        // The tangent follows u, which runs along x here.
+        assert!((rec.tangent - Vec3::X_HAT).near_zero());
+        assert!((rec.bitangent - Vec3::Y_HAT).near_zero());
+    }
+}
@@ -1,9 +1,9 @@
 use std::fmt;
 use std::ops::{Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign, Neg};
 
-use rand::prelude::*;
+use rand::Rng;
 
-use crate::render::random_f64;
+use crate::render::{random_f64, PI};
 
 /// Ray tracers are concerned principally with calculating the geometry
 /// of vectors in a three-dimensional space. Thus, it makes since to 
@@ -66,22 +66,18 @@ impl Vec3 {
         *self / self.length()
     }
 
-    #[allow(unused)]
     #[inline]
     pub fn coordinate_system_from(&self) -> (Vec3, Vec3, Vec3) {
         let v1 = self.unit_vector();
-        let v2: Vec3;
-        let v3: Vec3;
-
-        if v1.x.abs() > v1.y.abs() {
+        let v2 = if v1.x.abs() > v1.y.abs() {
             let norm_len = (v1.x * v1.x + v1.z * v1.z).sqrt();
-            v2 = Vec3::new(-v1.z, 0., v1.x) / norm_len;
+            Vec3::new(-v1.z, 0., v1.x) / norm_len
         } else {
             let norm_len = (v1.y * v1.y + v1.z * v1.z).sqrt();
-            v2 = Vec3::new(0., v1.z, -v1.y) / norm_len;
-        }
+            Vec3::new(0., v1.z, -v1.y) / norm_len
+        };
 
-        v3 = v1.cross(&v2);
+        let v3 = v1.cross(&v2);
         
         (v1, v2, v3)
     }
@@ -110,11 +106,24 @@ impl Vec3 {
         Self::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
     }
 
-    /// Unimplemented because I want to avoid this sort of thing.
-    /// That said, it could be doable with pattern matching.
-    #[allow(unused)]
+    /// Index (0, 1, 2 for x, y, z) of the largest component. This is the
+    /// pattern matching version of the book's `[]` access, which the BVH needs
+    /// to pick a split axis.
+    #[inline]
     pub fn max_dimension(&self) -> usize {
-        unimplemented!()
+        if self.x > self.y && self.x > self.z { 0 }
+        else if self.y > self.z { 1 }
+        else { 2 }
+    }
+
+    /// Component along axis 0, 1 or 2. Anything past 2 is treated as z.
+    #[inline]
+    pub fn component(&self, axis: usize) -> f64 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
     }
 
     /// Unimplemented because I don't quite understand what the idea is. I'm 
@@ -138,14 +147,14 @@ impl Vec3 {
         r_out_perp + r_out_parallel
     }
 
-    pub fn random(min: f64, max: f64, rng: &mut ThreadRng) -> Self {
+    pub fn random<R: Rng + ?Sized>(min: f64, max: f64, rng: &mut R) -> Self {
         let r1 = random_f64(min, max, rng);
         let r2 = random_f64(min, max, rng);
         let r3 = random_f64(min, max, rng);
         Vec3::new(r1, r2, r3)
     }
 
-    pub fn random_in_unit_sphere(rng: &mut ThreadRng) -> Self {
+    pub fn random_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Self {
         loop {
             let p = Self::random(-1.0, 1.0, rng);
             if p.length_squared() >= 1.0 { continue }
@@ -153,18 +162,30 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disk(rng: &mut ThreadRng) -> Self {
+    pub fn random_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> Self {
         loop {
             let p = Vec3::new(random_f64(-1.0, 1.0, rng), random_f64(-1.0, 1.0, rng), 0.0);
             if p.length_squared() < 1.0 { return p }
         }
     }
 
-    pub fn random_unit_vector(rng: &mut ThreadRng) -> Self {
+    pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Self::random_in_unit_sphere(rng).unit_vector()
     }
 
+    /// Cosine distributed direction about +z (Malley's method: uniform on the
+    /// disk, projected up onto the hemisphere).
+    pub fn random_cosine_direction<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let phi = 2.0 * PI * r1;
+        let r = r2.sqrt();
+        Vec3::new(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+    }
+
     pub const X_HAT: Self = Self { x: 1.0, y: 0.0, z: 0.0 };
+    pub const Y_HAT: Self = Self { x: 0.0, y: 1.0, z: 0.0 };
+    pub const Z_HAT: Self = Self { x: 0.0, y: 0.0, z: 1.0 };
 }
 
 impl Neg for Vec3 {